@@ -1,61 +1,70 @@
 use std::borrow::Cow;
 
 use figura::{
-    Argument, Context, Directive, EmptyDirective, Parser, ReplaceDirective, Template, Token, Value,
+    Context, DefaultParser, Directive, Parser, Scope, SyntaxError, Template, Token, Value,
 };
 
+#[derive(Default)]
 struct MathParser;
 
 impl Parser for MathParser {
-    fn parse(tokens: &[Token]) -> Option<Box<dyn Directive + Send + Sync>> {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
         match tokens {
-            [Token::Ident(var)] => Some(Box::new(ReplaceDirective(Argument::variable(
-                Cow::Owned(var.to_string()),
-            )))),
-
-            [Token::Ident(left), Token::Plus, Token::Ident(right)] => {
-                Some(Box::new(AddDirective {
-                    left: Cow::Owned(left.to_string()),
-                    right: Cow::Owned(right.to_string()),
-                }))
-            }
+            [Token::Ident(left), Token::Plus, Token::Ident(right)] => Ok(Box::new(AddDirective {
+                left: Cow::Owned(left.to_string()),
+                right: Cow::Owned(right.to_string()),
+            })),
 
             [Token::Ident(left), Token::Minus, Token::Ident(right)] => {
-                Some(Box::new(SubtractDirective {
+                Ok(Box::new(SubtractDirective {
                     left: Cow::Owned(left.to_string()),
                     right: Cow::Owned(right.to_string()),
                 }))
             }
 
             [Token::Ident(left), Token::Star, Token::Ident(right)] => {
-                Some(Box::new(MultiplyDirective {
+                Ok(Box::new(MultiplyDirective {
                     left: Cow::Owned(left.to_string()),
                     right: Cow::Owned(right.to_string()),
                 }))
             }
 
             [Token::Ident(left), Token::Slash, Token::Ident(right)] => {
-                Some(Box::new(DivideDirective {
+                Ok(Box::new(DivideDirective {
                     left: Cow::Owned(left.to_string()),
                     right: Cow::Owned(right.to_string()),
                 }))
             }
 
             [Token::Ident(var), Token::Star, Token::Int(num)] => {
-                Some(Box::new(MultiplyByLiteralDirective {
+                Ok(Box::new(MultiplyByLiteralDirective {
                     var: Cow::Owned(var.to_string()),
                     multiplier: num.parse().unwrap_or(1),
                 }))
             }
 
             [Token::Ident(var), Token::Plus, Token::Int(num)] => {
-                Some(Box::new(AddLiteralDirective {
+                Ok(Box::new(AddLiteralDirective {
                     var: Cow::Owned(var.to_string()),
                     addend: num.parse().unwrap_or(0),
                 }))
             }
 
-            _ => Some(Box::new(EmptyDirective)),
+            [
+                Token::Ident(_),
+                Token::Plus | Token::Minus | Token::Star | Token::Slash,
+                _,
+            ] => Err(SyntaxError::at(
+                2,
+                "expected a variable or an integer literal",
+            )),
+
+            [Token::Ident(_), Token::Unknown(_), ..] => {
+                Err(SyntaxError::at(1, "expected one of '+', '-', '*' or '/'"))
+            }
+
+            // Anything else is left to the parser chained after this one
+            _ => Err(SyntaxError::unrecognized()),
         }
     }
 }
@@ -66,8 +75,8 @@ struct AddDirective {
 }
 
 impl Directive for AddDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, figura::DirectiveError> {
-        let left_val = ctx
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, figura::DirectiveError> {
+        let left_val = scope
             .get(self.left.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -76,7 +85,7 @@ impl Directive for AddDirective {
             })
             .unwrap_or(0);
 
-        let right_val = ctx
+        let right_val = scope
             .get(self.right.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -95,8 +104,8 @@ struct SubtractDirective {
 }
 
 impl Directive for SubtractDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, figura::DirectiveError> {
-        let left_val = ctx
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, figura::DirectiveError> {
+        let left_val = scope
             .get(self.left.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -105,7 +114,7 @@ impl Directive for SubtractDirective {
             })
             .unwrap_or(0);
 
-        let right_val = ctx
+        let right_val = scope
             .get(self.right.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -124,8 +133,8 @@ struct MultiplyDirective {
 }
 
 impl Directive for MultiplyDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, figura::DirectiveError> {
-        let left_val = ctx
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, figura::DirectiveError> {
+        let left_val = scope
             .get(self.left.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -134,7 +143,7 @@ impl Directive for MultiplyDirective {
             })
             .unwrap_or(0);
 
-        let right_val = ctx
+        let right_val = scope
             .get(self.right.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -153,8 +162,8 @@ struct DivideDirective {
 }
 
 impl Directive for DivideDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, figura::DirectiveError> {
-        let left_val = ctx
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, figura::DirectiveError> {
+        let left_val = scope
             .get(self.left.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -163,7 +172,7 @@ impl Directive for DivideDirective {
             })
             .unwrap_or(0);
 
-        let right_val = ctx
+        let right_val = scope
             .get(self.right.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -182,8 +191,8 @@ struct MultiplyByLiteralDirective {
 }
 
 impl Directive for MultiplyByLiteralDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, figura::DirectiveError> {
-        let val = ctx
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, figura::DirectiveError> {
+        let val = scope
             .get(self.var.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -202,8 +211,8 @@ struct AddLiteralDirective {
 }
 
 impl Directive for AddLiteralDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, figura::DirectiveError> {
-        let val = ctx
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, figura::DirectiveError> {
+        let val = scope
             .get(self.var.as_ref())
             .and_then(|v| match v {
                 Value::Int(i) => Some(*i),
@@ -223,8 +232,15 @@ fn main() {
     ctx.insert("a", Value::Int(100));
     ctx.insert("b", Value::Int(25));
 
+    // Math rules first, everything else is handled by the default parser
+    let parser = MathParser.or(DefaultParser);
+
+    let template = Template::<'{', '}'>::compile_with(&parser, "x = {x}, y = {y}").unwrap();
+    println!("{}", template.format(&ctx).unwrap());
+
     let template =
-        Template::<'{', '}'>::compile_with_parser::<MathParser>("x = {x}, y = {y}").unwrap();
+        Template::<'{', '}'>::compile_with(&parser, "{x > y ? 'x' : 'y'} is bigger by {x - y}")
+            .unwrap();
     println!("{}", template.format(&ctx).unwrap());
 
     let template =
@@ -251,9 +267,13 @@ fn main() {
         Template::<'{', '}'>::compile_with_parser::<MathParser>("y + 10 = {y + 10}").unwrap();
     println!("{}", template.format(&ctx).unwrap());
 
-    let template = Template::<'{', '}'>::compile_with_parser::<MathParser>(
-        "Result: {x + y} + {a - b} = {x * 2}",
-    )
-    .unwrap();
+    let template =
+        Template::<'{', '}'>::compile_with(&MathParser, "Result: {x + y} + {a - b} = {x * 2}")
+            .unwrap();
     println!("{}", template.format(&ctx).unwrap());
+
+    // Unsupported operators are rejected with a reason
+    if let Err(err) = Template::<'{', '}'>::compile_with(&MathParser, "x % y = {x % y}") {
+        println!("{}", err);
+    }
 }
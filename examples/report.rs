@@ -1,4 +1,4 @@
-use figura::{Context, Template, Value};
+use figura::{Context, Environment, Locale, Template, Value};
 
 fn main() {
     let mut ctx = Context::new();
@@ -8,10 +8,11 @@ fn main() {
     ctx.insert("revenue", Value::Int(1250000));
     ctx.insert("expenses", Value::Int(890000));
     ctx.insert("profit", Value::Int(360000));
-    ctx.insert("growth", Value::Float(12.5));
+    ctx.insert("growth", Value::Float(0.125));
     ctx.insert("target_met", Value::Bool(true));
     ctx.insert("employees", Value::Int(42));
     ctx.insert("bar_width", Value::Int(40));
+    ctx.insert("profit_per_employee", Value::Float(360000.0 / 42.0));
 
     let template = Template::<'{', '}'>::compile(
         "\
@@ -22,10 +23,10 @@ fn main() {
 
 FINANCIAL SUMMARY
 {'-':70}
-Revenue:        ${revenue}
-Expenses:       ${expenses}
-Profit:         ${profit}
-Growth Rate:    {growth}%
+Revenue:        {revenue | >16,$}
+Expenses:       {expenses | >16,$}
+Profit:         {profit | >16,$}
+Growth Rate:    {growth | >16.1%}
 
 Performance:    {target_met ? '✓ TARGET MET' : '✗ TARGET MISSED'}
 Status:         {profit > 0 ? 'PROFITABLE' : 'LOSS'}
@@ -33,7 +34,8 @@ Status:         {profit > 0 ? 'PROFITABLE' : 'LOSS'}
 METRICS
 {'-':70}
 Employees:      {employees}
-Profit/Employee: ${profit != 0 ? '8571' : '0'}
+Revenue (exact): {revenue | ,.2}
+Profit/Employee: {profit_per_employee | ,$}
 
 VISUAL BREAKDOWN
 {'-':70}
@@ -43,7 +45,7 @@ Profit:   {'█':12}
 
 GROWTH INDICATOR
 {'-':70}
-{growth > 10.0 ? '↑ Strong Growth (★★)' : '→ Moderate Growth (★)'}
+{growth > 0.1 ? '↑ Strong Growth (★★)' : '→ Moderate Growth (★)'}
 
 {'=':70}
 Report Generated Successfully
@@ -52,5 +54,9 @@ Report Generated Successfully
     )
     .unwrap();
 
-    println!("{}", template.format(&ctx).unwrap());
+    let mut env = Environment::new();
+    println!("{}", template.format_with(&ctx, &env).unwrap());
+
+    env.set_locale(Locale::DE_DE);
+    println!("{}", template.format_with(&ctx, &env).unwrap());
 }
@@ -20,16 +20,19 @@ fn main() {
     ctx.insert("score3", Value::Int(92));
     ctx.insert("active3", Value::Bool(true));
 
+    ctx.insert("average", Value::Float((95.0 + 87.0 + 92.0) / 3.0));
+
     let template = Template::<'{', '}'>::compile(
         "\
 {title}
-{'-':60}
-| {'Name'}{' ':14} | {'Score'}{' ':9} | Status   |
-{'-':60}
-| {name1}{' ':14} | {score1}{' ':9} | {active1 ? '✓ Active' : '✗ Inactive'} |
-| {name2}{' ':16} | {score2}{' ':9} | {active2 ? '✓ Active' : '✗ Inactive'} |
-| {name3}{' ':12} | {score3}{' ':9} | {active3 ? '✓ Active' : '✗ Inactive'} |
-{'-':60}
+{'-':55}
+| {'Name' | <col1_width} | {'Score' | <col2_width} | {'Status' | <col3_width} |
+{'-':55}
+| {name1 | <col1_width} | {score1 | >col2_width} | {active1 ? 'Active' : 'Inactive' | ^col3_width} |
+| {name2 | <col1_width} | {score2 | >col2_width} | {active2 ? 'Active' : 'Inactive' | ^col3_width} |
+| {name3 | <col1_width} | {score3 | >col2_width} | {active3 ? 'Active' : 'Inactive' | ^col3_width} |
+{'-':55}
+Average score: {average | .2}
 Total: {!active2 ? '1' : '0'} inactive users
 ",
     )
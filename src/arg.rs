@@ -1,5 +1,5 @@
-use crate::{Context, Value, err::DirectiveError, traits::ToAstring};
-use std::borrow::Cow;
+use crate::{Value, env::Scope, err::DirectiveError, traits::ToAstring};
+use std::{borrow::Cow, fmt};
 
 /// An argument that can be resolved to a value at runtime.
 ///
@@ -17,8 +17,9 @@ use std::borrow::Cow;
 /// // Create a variable reference
 /// let var = Argument::variable(Cow::Borrowed("username"));
 ///
-/// // Create a literal value
-/// let lit = Argument::literal(Cow::Borrowed("42"));
+/// // Create literal values
+/// let text = Argument::literal(Cow::Borrowed("hello"));
+/// let number = Argument::literal(42);
 /// ```
 #[derive(Debug, Clone)]
 pub enum Argument {
@@ -30,9 +31,9 @@ pub enum Argument {
 
     /// A literal value embedded directly in the template.
     ///
-    /// This value is used as-is and parsed into the required type
-    /// when the argument is resolved.
-    Literal(Cow<'static, str>),
+    /// The parser keeps the literal's type (string, integer, float or
+    /// boolean), so numbers are parsed once when the template is compiled.
+    Literal(Value),
 
     /// A complex expression that evaluates to a value.
     ///
@@ -44,8 +45,7 @@ pub enum Argument {
 /// Comparison operators for use in conditional expressions.
 ///
 /// These operators compare two values and produce a boolean result.
-/// Numeric comparisons are performed when both operands can be parsed as numbers;
-/// otherwise, string comparison is used.
+/// See [`Value::compare`] for which values can be compared.
 #[derive(Debug, Clone)]
 pub enum ComparisonOp {
     /// Equality: `==`
@@ -62,24 +62,43 @@ pub enum ComparisonOp {
     LessThanEquals,
 }
 
+/// Arithmetic operators for computing values in expressions.
+///
+/// Integers stay integers, and any float operand makes the result a float.
+/// `+` also concatenates two strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    /// Addition or string concatenation: `+`
+    Add,
+    /// Subtraction: `-`
+    Subtract,
+    /// Multiplication: `*`
+    Multiply,
+    /// Division: `/`, truncating when both operands are integers
+    Divide,
+}
+
 /// An expression that can be evaluated to produce a value.
 ///
-/// Expressions support comparison operations and logical negation.
-/// They are typically used in conditional directives to determine
-/// which branch to take.
+/// Expressions support comparison operations, arithmetic, logical negation,
+/// function calls and null coalescing. Comparisons and negations are typically used in
+/// conditional directives to determine which branch to take.
 ///
 /// # Examples
 ///
 /// ```text
 /// {x == 5 ? "yes" : "no"}      // Comparison expression
+/// {price * qty}                // Arithmetic expression
 /// {!active ? "inactive" : "active"}  // NOT expression
+/// {max(a, b)}                  // Function call
+/// {nickname ?? name}           // Null coalescing
 /// ```
 #[derive(Debug, Clone)]
 pub enum Expression {
     /// A binary comparison between two arguments.
     ///
     /// Evaluates to a boolean by comparing the left and right arguments
-    /// using the specified operator. Supports both numeric and string comparisons.
+    /// using the specified operator, following [`Value::compare`].
     Comparison {
         /// Left-hand side of the comparison
         left: Argument,
@@ -88,11 +107,112 @@ pub enum Expression {
         /// Right-hand side of the comparison
         right: Argument,
     },
+    /// A binary arithmetic operation between two arguments.
+    ///
+    /// Integer arithmetic is checked: overflow and division by zero are
+    /// errors rather than wrapping or panicking.
+    Arithmetic {
+        /// Left-hand side of the operation
+        left: Argument,
+        /// The arithmetic operator
+        op: ArithmeticOp,
+        /// Right-hand side of the operation
+        right: Argument,
+    },
     /// Logical NOT operation.
     ///
     /// Negates the boolean value of the argument. The argument must
     /// resolve to a boolean or truthy/falsy value.
     Not(Argument),
+    /// A call to a function registered on the [`Environment`](crate::Environment),
+    /// or to a macro of the template, which takes precedence.
+    ///
+    /// The arguments are evaluated first and handed to the function, which
+    /// checks their number and types.
+    Call {
+        /// The name of the function
+        name: Cow<'static, str>,
+        /// The call arguments
+        args: Vec<Argument>,
+    },
+    /// Null coalescing: `a ?? b ?? c`.
+    ///
+    /// Evaluates to the first argument that is present and not null. Missing
+    /// variables fall through instead of raising `DirectiveError::NotFound`;
+    /// only the last argument is resolved as-is.
+    Coalesce(Vec<Argument>),
+}
+
+impl ComparisonOp {
+    /// Returns the operator as written in templates, e.g. `">="`.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Equals => "==",
+            Self::NotEquals => "!=",
+            Self::GreaterThan => ">",
+            Self::LessThan => "<",
+            Self::GreaterThanEquals => ">=",
+            Self::LessThanEquals => "<=",
+        }
+    }
+}
+
+impl ArithmeticOp {
+    /// Returns the operator as written in templates, e.g. `"*"`.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+        }
+    }
+
+    /// Applies the operator to two values.
+    fn apply(self, left: &Value, right: &Value) -> Result<Value, DirectiveError> {
+        let overflow = || DirectiveError::Overflow { op: self.symbol() };
+
+        match (left, right) {
+            (Value::Int(_) | Value::Float(_), Value::Int(0))
+            | (Value::Int(_) | Value::Float(_), Value::Float(0.0))
+                if self == Self::Divide =>
+            {
+                Err(DirectiveError::DivisionByZero)
+            }
+            (Value::Int(a), Value::Int(b)) => match self {
+                Self::Add => a.checked_add(*b),
+                Self::Subtract => a.checked_sub(*b),
+                Self::Multiply => a.checked_mul(*b),
+                Self::Divide => a.checked_div(*b),
+            }
+            .map(Value::Int)
+            .ok_or_else(overflow),
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                let a = f64::from_value(left).unwrap_or_default();
+                let b = f64::from_value(right).unwrap_or_default();
+
+                Ok(Value::Float(match self {
+                    Self::Add => a + b,
+                    Self::Subtract => a - b,
+                    Self::Multiply => a * b,
+                    Self::Divide => a / b,
+                }))
+            }
+            (Value::Safe(a), Value::Safe(b)) if self == Self::Add => {
+                Ok(Value::Safe(Cow::Owned(format!("{}{}", a, b))))
+            }
+            (Value::Str(a) | Value::Safe(a), Value::Str(b) | Value::Safe(b))
+                if self == Self::Add =>
+            {
+                Ok(Value::Str(Cow::Owned(format!("{}{}", a, b))))
+            }
+            _ => Err(DirectiveError::InvalidOperands {
+                left: left.type_name().to_string(),
+                op: self.symbol(),
+                right: right.type_name().to_string(),
+            }),
+        }
+    }
 }
 
 impl Argument {
@@ -109,9 +229,9 @@ impl Argument {
     ///
     /// # Arguments
     ///
-    /// * `s` - The literal string value
-    pub fn literal(s: Cow<'static, str>) -> Self {
-        Self::Literal(s)
+    /// * `value` - The literal value, e.g. a string, an `i64` or a `Value`
+    pub fn literal(value: impl Into<Value>) -> Self {
+        Self::Literal(value.into())
     }
 
     /// Creates an expression argument from an Expression.
@@ -137,6 +257,17 @@ impl Argument {
         Self::Expression(Box::new(Expression::Comparison { left, op, right }))
     }
 
+    /// Creates an arithmetic expression argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - Left-hand side argument
+    /// * `op` - Arithmetic operator
+    /// * `right` - Right-hand side argument
+    pub fn arithmetic(left: Self, op: ArithmeticOp, right: Self) -> Self {
+        Self::Expression(Box::new(Expression::Arithmetic { left, op, right }))
+    }
+
     /// Creates a NOT expression argument.
     ///
     /// This is a convenience method for negating an argument's boolean value.
@@ -147,6 +278,102 @@ impl Argument {
     pub fn not(arg: Self) -> Self {
         Self::Expression(Box::new(Expression::Not(arg)))
     }
+
+    /// Creates a function call expression argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function to call
+    /// * `args` - The call arguments
+    pub fn call(name: Cow<'static, str>, args: Vec<Self>) -> Self {
+        Self::Expression(Box::new(Expression::Call { name, args }))
+    }
+
+    /// Creates a null coalescing expression argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The candidates, tried in order
+    pub fn coalesce(args: Vec<Self>) -> Self {
+        Self::Expression(Box::new(Expression::Coalesce(args)))
+    }
+}
+
+/// Writes the argument as it would appear in a template, e.g. `max(a, 'b')`.
+impl fmt::Display for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Variable(name) => f.write_str(name),
+            Self::Literal(Value::Str(s) | Value::Safe(s)) => {
+                write!(f, "'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+            }
+            Self::Literal(Value::Int(i)) => f.write_str(&i.to_astring()),
+            Self::Literal(Value::Float(v)) => f.write_str(&v.to_astring()),
+            Self::Literal(Value::Bool(b)) => write!(f, "{}", b),
+            Self::Literal(Value::Null) => f.write_str("null"),
+            Self::Expression(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+/// Writes the expression as it would appear in a template, e.g. `a + b > 3`.
+///
+/// Operands that are themselves operations are parenthesized.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter<'_>, arg: &Argument| match arg {
+            Argument::Expression(expr)
+                if matches!(
+                    **expr,
+                    Self::Comparison { .. } | Self::Arithmetic { .. } | Self::Coalesce(_)
+                ) =>
+            {
+                write!(f, "({})", expr)
+            }
+            arg => write!(f, "{}", arg),
+        };
+
+        match self {
+            Self::Comparison { left, op, right } => {
+                operand(f, left)?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, right)
+            }
+            Self::Arithmetic { left, op, right } => {
+                operand(f, left)?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, right)
+            }
+            Self::Not(arg) => {
+                f.write_str("!")?;
+                operand(f, arg)
+            }
+            Self::Call { name, args } => {
+                write!(f, "{}(", name)?;
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+
+                    write!(f, "{}", arg)?;
+                }
+
+                f.write_str(")")
+            }
+            Self::Coalesce(args) => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ?? ")?;
+                    }
+
+                    operand(f, arg)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
 }
 
 /// Types that can be resolved from template arguments.
@@ -210,7 +437,7 @@ impl Argument {
     ///
     /// # Arguments
     ///
-    /// * `ctx` - The template context containing variable values
+    /// * `scope` - The render scope containing variable values and functions
     ///
     /// # Returns
     ///
@@ -227,21 +454,21 @@ impl Argument {
     /// # Examples
     ///
     /// ```rust
-    /// use figura::{Argument, Context, Value};
+    /// use figura::{Argument, Context, Environment, Scope, Value};
     /// use std::borrow::Cow;
-    /// use std::collections::HashMap;
     ///
-    /// let mut ctx = HashMap::new();
+    /// let mut ctx = Context::new();
     /// ctx.insert("count", Value::Int(42));
+    /// let env = Environment::new();
     ///
     /// let arg = Argument::variable(Cow::Borrowed("count"));
-    /// let value: i64 = arg.resolve_as(&ctx).unwrap();
+    /// let value: i64 = arg.resolve_as(&Scope::new(&ctx, &env)).unwrap();
     /// assert_eq!(value, 42);
     /// ```
-    pub fn resolve_as<T: Resolvable>(&self, ctx: &Context) -> Result<T, DirectiveError> {
+    pub fn resolve_as<T: Resolvable>(&self, scope: &Scope) -> Result<T, DirectiveError> {
         match self {
             Self::Variable(name) => {
-                if let Some(value) = ctx.get(name.as_ref()) {
+                if let Some(value) = scope.get(name.as_ref()) {
                     if let Some(parsed) = T::from_value(value) {
                         return Ok(parsed);
                     }
@@ -262,37 +489,93 @@ impl Argument {
             }
 
             Self::Literal(value) => {
-                // Just try to parse it
-                T::from_string_slice(value).map_err(|err| DirectiveError::ParseError {
-                    value: value.to_string(),
-                    type_name: T::TYPE_NAME,
-                    message: err,
-                })
+                if let Some(parsed) = T::from_value(value) {
+                    return Ok(parsed);
+                }
+
+                match value {
+                    // String literals get the parser's reason for rejecting them
+                    Value::Str(s) | Value::Safe(s) => {
+                        T::from_string_slice(s).map_err(|err| DirectiveError::ParseError {
+                            value: s.to_string(),
+                            type_name: T::TYPE_NAME,
+                            message: err,
+                        })
+                    }
+                    _ => Err(DirectiveError::TypeError {
+                        name: self.to_string(),
+                        expected: T::TYPE_NAME,
+                        found: value.type_name().to_string(),
+                    }),
+                }
             }
 
             Self::Expression(expr) => {
                 // Evaluate the expression and convert to the requested type
-                let result = expr.evaluate(ctx)?;
+                let result = expr.evaluate(scope)?;
 
                 T::from_value(&result).ok_or_else(|| DirectiveError::TypeError {
-                    name: "expression".to_string(),
+                    name: expr.to_string(),
                     expected: T::TYPE_NAME,
                     found: result.type_name().to_string(),
                 })
             }
         }
     }
+
+    /// Resolves this argument to a runtime value without converting it.
+    ///
+    /// Variables are cloned from the context, literals are cloned and
+    /// expressions are evaluated. Used to hand arguments to functions, which
+    /// convert them to the types they need.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DirectiveError::NotFound` if a variable is missing, or any error
+    /// raised while evaluating an expression.
+    pub fn resolve_value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        match self {
+            Self::Variable(name) => {
+                scope
+                    .get(name.as_ref())
+                    .cloned()
+                    .ok_or_else(|| DirectiveError::NotFound {
+                        name: name.to_string(),
+                        type_name: "value",
+                    })
+            }
+            Self::Literal(value) => Ok(value.clone()),
+            Self::Expression(expr) => expr.evaluate(scope),
+        }
+    }
+
+    /// Resolves this argument like [`resolve_value`](Self::resolve_value), marking
+    /// string literals as [`Value::Safe`].
+    ///
+    /// String literals are written by the template author, so escaping treats
+    /// them like the text around directives. This holds for the operands of
+    /// `??` and arithmetic too, which [`Expression::evaluate`] resolves this way.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`resolve_value`](Self::resolve_value).
+    pub fn resolve_output(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        match self {
+            Self::Literal(Value::Str(s)) => Ok(Value::Safe(s.clone())),
+            _ => self.resolve_value(scope),
+        }
+    }
 }
 
 impl Expression {
     /// Evaluates this expression to produce a runtime value.
     ///
-    /// Comparison expressions attempt numeric comparison when both sides
-    /// can be parsed as floats; otherwise, they fall back to string comparison.
+    /// Comparisons follow [`Value::compare`]: numbers compare with numbers,
+    /// strings with strings and booleans with booleans.
     ///
     /// # Arguments
     ///
-    /// * `ctx` - The template context for resolving variables
+    /// * `scope` - The render scope for resolving variables and functions
     ///
     /// # Returns
     ///
@@ -301,60 +584,112 @@ impl Expression {
     ///
     /// # Errors
     ///
-    /// Returns an error if any sub-argument fails to resolve.
-    pub fn evaluate(&self, ctx: &Context) -> Result<crate::Value, DirectiveError> {
+    /// Returns an error if any sub-argument fails to resolve, or if a called
+    /// function is unknown or rejects its arguments, or
+    /// `DirectiveError::Incomparable` if the two sides of a comparison cannot
+    /// be compared. Arithmetic fails with `DirectiveError::InvalidOperands`,
+    /// `DirectiveError::DivisionByZero` or `DirectiveError::Overflow`. Each
+    /// evaluation uses one unit of fuel, failing with
+    /// `DirectiveError::LimitExceeded` once it is spent.
+    pub fn evaluate(&self, scope: &Scope) -> Result<crate::Value, DirectiveError> {
+        scope.use_fuel()?;
+
         match self {
             Self::Comparison { left, op, right } => {
-                // Try to resolve both sides as strings first, then try numeric comparison
-                let left_str = left.resolve_as::<Cow<'static, str>>(ctx)?;
-                let right_str = right.resolve_as::<Cow<'static, str>>(ctx)?;
+                let left = left.resolve_value(scope)?;
+                let right = right.resolve_value(scope)?;
+
+                let ordering = match left.compare(&right) {
+                    Some(ordering) => ordering,
+                    // Anything can be checked for equality with null
+                    None if matches!(left, Value::Null) || matches!(right, Value::Null) => match op
+                    {
+                        ComparisonOp::Equals => return Ok(Value::Bool(false)),
+                        ComparisonOp::NotEquals => return Ok(Value::Bool(true)),
+                        _ => return Err(Self::incomparable(&left, op, &right)),
+                    },
+                    None => return Err(Self::incomparable(&left, op, &right)),
+                };
 
                 let result = match op {
-                    ComparisonOp::Equals => left_str == right_str,
-                    ComparisonOp::NotEquals => left_str != right_str,
-                    ComparisonOp::GreaterThan => {
-                        // Try numeric comparison
-                        // Treating anything as a float
-                        if let (Ok(l), Ok(r)) = (left_str.parse::<f64>(), right_str.parse::<f64>())
-                        {
-                            l > r
-                        } else {
-                            left_str > right_str
-                        }
-                    }
-                    ComparisonOp::LessThan => {
-                        if let (Ok(l), Ok(r)) = (left_str.parse::<f64>(), right_str.parse::<f64>())
-                        {
-                            l < r
-                        } else {
-                            left_str < right_str
-                        }
-                    }
-                    ComparisonOp::GreaterThanEquals => {
-                        if let (Ok(l), Ok(r)) = (left_str.parse::<f64>(), right_str.parse::<f64>())
-                        {
-                            l >= r
-                        } else {
-                            left_str >= right_str
-                        }
-                    }
-                    ComparisonOp::LessThanEquals => {
-                        if let (Ok(l), Ok(r)) = (left_str.parse::<f64>(), right_str.parse::<f64>())
-                        {
-                            l <= r
-                        } else {
-                            left_str <= right_str
-                        }
-                    }
+                    ComparisonOp::Equals => ordering.is_eq(),
+                    ComparisonOp::NotEquals => ordering.is_ne(),
+                    ComparisonOp::GreaterThan => ordering.is_gt(),
+                    ComparisonOp::LessThan => ordering.is_lt(),
+                    ComparisonOp::GreaterThanEquals => ordering.is_ge(),
+                    ComparisonOp::LessThanEquals => ordering.is_le(),
                 };
 
                 Ok(Value::Bool(result))
             }
+            Self::Arithmetic { left, op, right } => {
+                // Joining two string literals keeps the result safe
+                let left = left.resolve_output(scope)?;
+                let right = right.resolve_output(scope)?;
+
+                op.apply(&left, &right)
+            }
             Self::Not(arg) => {
-                let value = arg.resolve_as::<bool>(ctx)?;
+                let value = arg.resolve_as::<bool>(scope)?;
 
                 Ok(Value::Bool(!value))
             }
+            Self::Call { name, args } => {
+                // Macros of the template shadow functions of the environment.
+                // String literals stay safe, as in the rest of the template.
+                if let Some(macro_def) = scope.macro_named(name) {
+                    let values = args
+                        .iter()
+                        .map(|arg| arg.resolve_output(scope))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    return macro_def.call(values, scope);
+                }
+
+                let function =
+                    scope
+                        .env()
+                        .function(name)
+                        .ok_or_else(|| DirectiveError::UnknownFunction {
+                            name: name.to_string(),
+                        })?;
+
+                let values = args
+                    .iter()
+                    .map(|arg| arg.resolve_value(scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                function.call(name, &values)
+            }
+            Self::Coalesce(args) => {
+                let Some((last, rest)) = args.split_last() else {
+                    return Ok(Value::Null);
+                };
+
+                // Operands resolve like directive output, so literal
+                // fallbacks are not escaped
+                for arg in rest {
+                    match arg.resolve_output(scope) {
+                        Ok(Value::Null) => continue,
+                        Err(err) if matches!(err.root(), DirectiveError::NotFound { .. }) => {
+                            continue;
+                        }
+                        result => return result,
+                    }
+                }
+
+                last.resolve_output(scope)
+            }
+        }
+    }
+}
+
+impl Expression {
+    fn incomparable(left: &Value, op: &ComparisonOp, right: &Value) -> DirectiveError {
+        DirectiveError::Incomparable {
+            left: left.type_name().to_string(),
+            op: op.symbol(),
+            right: right.type_name().to_string(),
         }
     }
 }
@@ -365,13 +700,14 @@ impl Resolvable for Cow<'static, str> {
     /// Converts any Value type to a string representation.
     ///
     /// All value types can be converted to strings, making this
-    /// conversion infallible.
+    /// conversion infallible. Null becomes an empty string.
     fn from_value(value: &Value) -> Option<Self> {
         match value {
-            Value::Str(v) => Some(v.clone()),
+            Value::Str(v) | Value::Safe(v) => Some(v.clone()),
             Value::Int(v) => Some(Cow::Owned(v.to_astring())),
             Value::Float(v) => Some(Cow::Owned(v.to_astring())),
             Value::Bool(v) => Some(Cow::Owned(v.to_string())),
+            Value::Null => Some(Cow::Borrowed("")),
         }
     }
 
@@ -390,11 +726,12 @@ impl Resolvable for i64 {
     /// - Booleans become 0 or 1
     fn from_value(value: &Value) -> Option<Self> {
         match value {
-            Value::Str(v) => v.parse().ok(),
+            Value::Str(v) | Value::Safe(v) => v.parse().ok(),
 
             Value::Int(v) => Some(*v),
             Value::Float(v) => Some(*v as Self),
             Value::Bool(v) => Some(*v as Self),
+            Value::Null => None,
         }
     }
 
@@ -413,11 +750,12 @@ impl Resolvable for f64 {
     /// - Booleans become 0.0 or 1.0
     fn from_value(value: &Value) -> Option<Self> {
         match value {
-            Value::Str(v) => v.parse().ok(),
+            Value::Str(v) | Value::Safe(v) => v.parse().ok(),
 
             Value::Int(v) => Some(*v as Self),
             Value::Float(v) => Some(*v),
             Value::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+            Value::Null => None,
         }
     }
 
@@ -434,13 +772,15 @@ impl Resolvable for bool {
     /// - Strings are parsed ("true"/"false")
     /// - Integers use zero/non-zero semantics
     /// - Floats use zero/non-zero semantics
+    /// - Null is false
     fn from_value(value: &Value) -> Option<Self> {
         match value {
-            Value::Str(v) => v.parse().ok(),
+            Value::Str(v) | Value::Safe(v) => v.parse().ok(),
 
             Value::Int(v) => Some(*v != 0),
             Value::Float(v) => Some(*v != 0.0),
             Value::Bool(v) => Some(*v),
+            Value::Null => Some(false),
         }
     }
 
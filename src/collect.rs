@@ -0,0 +1,134 @@
+//! Rendering that keeps going past failing directives.
+//!
+//! [`Template::format_collect`](crate::Template::format_collect) writes the
+//! environment's error marker in place of every directive that fails and
+//! returns the errors together with the output, so template authors see all
+//! the problems of a template at once.
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+};
+
+use crate::err::{DirectiveError, Location};
+
+/// A directive that failed while rendering.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, DirectiveError, Environment, Span, Template};
+///
+/// let tmpl = Template::<'{', '}'>::compile("Hi {name}!").unwrap();
+/// let rendered = tmpl.format_collect(&Context::new(), &Environment::new());
+///
+/// let error = &rendered.errors[0];
+/// let location = error.location.as_ref().unwrap();
+/// assert_eq!(location.span, Span::new(4, 8));
+/// assert_eq!(location.directive, "{name}");
+/// assert!(matches!(error.error, DirectiveError::NotFound { .. }));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RenderError {
+    /// Why the directive failed
+    pub error: DirectiveError,
+    /// Where the failing directive is, or `None` if the error is not tied to
+    /// a directive
+    pub location: Option<Location>,
+}
+
+impl From<DirectiveError> for RenderError {
+    /// Splits the error from its location.
+    fn from(error: DirectiveError) -> Self {
+        match error {
+            DirectiveError::At { location, source } => Self {
+                error: source.into_root(),
+                location: Some(location),
+            },
+            error => Self {
+                error,
+                location: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {}", location, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The output of a render that collected its errors.
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    /// The output, with the error marker in place of failing directives
+    pub output: String,
+    /// The errors, in the order they occurred
+    pub errors: Vec<RenderError>,
+}
+
+impl Rendered {
+    /// Returns `true` if no directive failed.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Gathers the errors of a render, shared by every fragment it renders.
+#[derive(Debug, Default)]
+pub(crate) struct Collector {
+    errors: RefCell<Vec<RenderError>>,
+    /// Set once a limit stopped the render
+    aborted: Cell<bool>,
+}
+
+impl Collector {
+    /// Records the error of a failing directive.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error back if the render must stop, which is the case for
+    /// `DirectiveError::LimitExceeded`: going on would only exceed it further.
+    /// It is recorded once, by the directive that raised it.
+    pub(crate) fn recover(&self, error: DirectiveError) -> Result<(), DirectiveError> {
+        let fatal = matches!(error.root(), DirectiveError::LimitExceeded { .. });
+
+        if fatal && self.aborted.replace(true) {
+            return Err(error);
+        }
+
+        self.errors
+            .borrow_mut()
+            .push(RenderError::from(error.clone()));
+
+        if fatal { Err(error) } else { Ok(()) }
+    }
+
+    /// Builds the result of a render from its outcome.
+    ///
+    /// A render that failed as a whole has no output.
+    pub(crate) fn finish(self, result: Result<String, DirectiveError>) -> Rendered {
+        let mut errors = self.errors.into_inner();
+
+        let output = match result {
+            Ok(output) => output,
+            Err(_) if self.aborted.get() => String::new(),
+            Err(error) => {
+                errors.push(RenderError::from(error));
+                String::new()
+            }
+        };
+
+        Rendered { output, errors }
+    }
+}
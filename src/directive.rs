@@ -1,6 +1,11 @@
-use crate::Context;
-use crate::arg::Argument;
+use crate::Value;
+use crate::arg::{Argument, Resolvable};
+use crate::env::{Locals, Scope};
 use crate::err::DirectiveError;
+use crate::format::FormatSpec;
+use crate::fragment::Fragment;
+use crate::locale::PluralCategory;
+use crate::traits::ToAstring;
 use std::borrow::Cow;
 
 /// A template directive that can be executed to produce output.
@@ -15,11 +20,11 @@ use std::borrow::Cow;
 /// Directives are trait objects stored in the compiled template and executed
 /// sequentially during the `format` operation.
 pub trait Directive {
-    /// Executes this directive with the given context.
+    /// Executes this directive in the given scope.
     ///
     /// # Arguments
     ///
-    /// * `ctx` - The template context containing variable values
+    /// * `scope` - The render scope containing variable values and functions
     ///
     /// # Returns
     ///
@@ -30,7 +35,21 @@ pub trait Directive {
     ///
     /// Returns an error if the directive cannot be executed, such as when
     /// a required variable is missing or has an incompatible type.
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, DirectiveError>;
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError>;
+
+    /// Evaluates this directive to a value instead of text.
+    ///
+    /// Used by [`FormatDirective`] so that format specs can tell numbers from
+    /// strings, and by templates that escape their output, which leave
+    /// [`Value::Safe`] values alone. The default implementation returns the
+    /// output of [`exec`](Directive::exec) as a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`exec`](Directive::exec).
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        self.exec(scope).map(Value::Str)
+    }
 }
 
 /// A directive that produces no output.
@@ -44,7 +63,7 @@ pub trait Directive {
 pub struct EmptyDirective;
 
 impl Directive for EmptyDirective {
-    fn exec(&self, _ctx: &Context) -> Result<Cow<'static, str>, DirectiveError> {
+    fn exec(&self, _scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
         Ok(Cow::Borrowed(""))
     }
 }
@@ -67,7 +86,7 @@ impl Directive for EmptyDirective {
 pub struct LiteralDirective(pub Cow<'static, str>);
 
 impl Directive for LiteralDirective {
-    fn exec(&self, _ctx: &Context) -> Result<Cow<'static, str>, DirectiveError> {
+    fn exec(&self, _scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
         Ok(self.0.clone())
     }
 }
@@ -91,8 +110,12 @@ impl Directive for LiteralDirective {
 pub struct ReplaceDirective(pub Argument);
 
 impl Directive for ReplaceDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, DirectiveError> {
-        self.0.resolve_as::<Cow<'static, str>>(ctx)
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        self.0.resolve_as::<Cow<'static, str>>(scope)
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        self.0.resolve_output(scope)
     }
 }
 
@@ -118,14 +141,103 @@ impl Directive for ReplaceDirective {
 /// Returns an error if:
 /// - The pattern argument cannot be resolved to a string
 /// - The count argument cannot be resolved to an integer
+/// - The count or the output exceeds the environment's [`Limits`](crate::Limits)
+///
+/// A negative count repeats nothing.
 pub struct RepeatDirective(pub Argument, pub Argument);
 
 impl Directive for RepeatDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, DirectiveError> {
-        let pattern = self.0.resolve_as::<Cow<'static, str>>(ctx)?;
-        let count = self.1.resolve_as::<i64>(ctx)?;
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let pattern = self.0.resolve_as::<Cow<'static, str>>(scope)?;
+        let count = usize::try_from(self.1.resolve_as::<i64>(scope)?).unwrap_or(0);
+
+        scope.check_repeat(count)?;
+        // A length that overflows is beyond any output limit
+        let len = pattern.len().saturating_mul(count);
+        scope.check_output(len)?;
+
+        Ok(Cow::Owned(pattern.repeat(count)))
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        let text = self.exec(scope)?;
 
-        Ok(Cow::Owned(pattern.repeat(count as usize)))
+        Ok(match self.0 {
+            Argument::Literal(Value::Str(_)) => Value::Safe(text),
+            _ => Value::Str(text),
+        })
+    }
+}
+
+/// A directive that renders a fragment of the template several times.
+///
+/// Syntax: `{repeat count [as name] [sep separator]}...{endrepeat}`. Created
+/// by the template compiler, so it works with any parser. The body sees the
+/// 0-based iteration index as `name`, or `index` if no name is given, and
+/// the separator is written between iterations. A count below one renders
+/// nothing.
+///
+/// # Examples
+///
+/// ```text
+/// Template: "{repeat n as i sep ', '}${i + 1}{endrepeat}"
+/// With context: n = 3
+/// Produces: "$1, $2, $3"
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the count cannot be resolved to an integer, if the
+/// separator cannot be resolved, if the count or the output exceeds the
+/// environment's [`Limits`](crate::Limits), or any error raised by the body.
+pub(crate) struct RepeatBodyDirective {
+    /// The number of iterations
+    pub(crate) count: Argument,
+    /// The variable holding the iteration index
+    pub(crate) index: Cow<'static, str>,
+    /// Written between iterations
+    pub(crate) separator: Option<Argument>,
+    /// The repeated fragment
+    pub(crate) body: Fragment,
+}
+
+impl Directive for RepeatBodyDirective {
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let count = usize::try_from(self.count.resolve_as::<i64>(scope)?).unwrap_or(0);
+
+        scope.check_repeat(count)?;
+
+        let separator = match &self.separator {
+            Some(separator) if count > 1 => match separator.resolve_output(scope)? {
+                Value::Safe(text) => text,
+                value => {
+                    let text = Cow::<'static, str>::from_value(&value).unwrap_or_default();
+                    Cow::Owned(scope.escape().escape(&text).into_owned())
+                }
+            },
+            _ => Cow::Borrowed(""),
+        };
+
+        let mut output = String::new();
+
+        for i in 0..count {
+            if i > 0 {
+                output.push_str(&separator);
+            }
+
+            let locals = Locals::new(vec![(self.index.clone(), Value::Int(i as i64))]);
+            let vars = scope.variables();
+            let scope = scope.with_variables(vars.with_locals(&locals));
+            output.push_str(&self.body.render(&scope)?);
+            scope.check_output(output.len())?;
+        }
+
+        Ok(Cow::Owned(output))
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        // The body escapes its own output
+        self.exec(scope).map(Value::Safe)
     }
 }
 
@@ -167,13 +279,180 @@ pub struct ConditionalDirective {
 }
 
 impl Directive for ConditionalDirective {
-    fn exec(&self, ctx: &Context) -> Result<Cow<'static, str>, DirectiveError> {
-        let cond_value = self.cond.resolve_as::<bool>(ctx)?;
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let cond_value = self.cond.resolve_as::<bool>(scope)?;
 
         if cond_value {
-            self.if_true.resolve_as::<Cow<'static, str>>(ctx)
+            self.if_true.resolve_as::<Cow<'static, str>>(scope)
+        } else {
+            self.if_false.resolve_as::<Cow<'static, str>>(scope)
+        }
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        if self.cond.resolve_as::<bool>(scope)? {
+            self.if_true.resolve_output(scope)
         } else {
-            self.if_false.resolve_as::<Cow<'static, str>>(ctx)
+            self.if_false.resolve_output(scope)
         }
     }
 }
+
+/// A directive whose value is rendered through a [`FormatSpec`].
+///
+/// Created by the template compiler for directives followed by `| spec`, so it
+/// works with directives from any parser.
+///
+/// # Examples
+///
+/// ```text
+/// Template: "{price | >8.2}"
+/// With context: price = 3.5
+/// Produces: "    3.50"
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the inner directive fails, or if a width or precision
+/// variable is missing or not a non-negative integer.
+pub struct FormatDirective(pub Box<dyn Directive + Send + Sync>, pub FormatSpec);
+
+impl Directive for FormatDirective {
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let value = self.0.value(scope)?;
+
+        self.1.apply(&value, scope).map(Cow::Owned)
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        let value = self.0.value(scope)?;
+        let text = Cow::Owned(self.1.apply(&value, scope)?);
+
+        // Padding a safe value keeps it safe
+        Ok(match value {
+            Value::Safe(_) => Value::Safe(text),
+            _ => Value::Str(text),
+        })
+    }
+}
+
+/// A directive that renders another named template.
+///
+/// Syntax: `{> name}` or `{> 'name'}`. Created by the template compiler, so it
+/// works with any parser. The included template is rendered by the scope's
+/// [`IncludeResolver`](crate::IncludeResolver), usually a
+/// [`Loader`](crate::Loader), with the same context. Its output is already
+/// escaped by the included template and is not escaped again.
+///
+/// # Examples
+///
+/// ```text
+/// Template: "{> header}<main>{body}</main>"
+/// With template "header": "<h1>{title}</h1>"
+/// With context: title = "News", body = "..."
+/// Produces: "<h1>News</h1><main>...</main>"
+/// ```
+///
+/// # Errors
+///
+/// Returns `DirectiveError::Include` if the scope has no resolver or the
+/// template cannot be loaded, `DirectiveError::LimitExceeded` if includes are
+/// nested too deeply, and any error raised while rendering it.
+pub struct IncludeDirective(pub Cow<'static, str>);
+
+impl Directive for IncludeDirective {
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let loader = scope.loader().ok_or_else(|| DirectiveError::Include {
+            name: self.0.to_string(),
+            reason: "no loader is available".to_string(),
+        })?;
+
+        loader.include(&self.0, &scope.nested()?).map(Cow::Owned)
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        self.exec(scope).map(Value::Safe)
+    }
+}
+
+/// Which counts a plural form applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluralSelector {
+    /// An exact count, written `=0`
+    Exact(i64),
+    /// A CLDR category, written `one`, `few`, ...
+    Category(PluralCategory),
+}
+
+/// A directive that picks a form based on a count.
+///
+/// Syntax: `{plural count one: '# item', other: '# items'}`. Exact counts
+/// (`=0: 'no items'`) are tried first, then the category given by the
+/// [`PluralRule`](crate::PluralRule) of the environment's locale, and finally
+/// `other`. A `#` in the selected form is replaced by the count.
+///
+/// # Examples
+///
+/// ```text
+/// Template: "{plural n =0: 'no files', one: '# file', other: '# files'}"
+/// With context: n = 3
+/// Produces: "3 files"
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - The count cannot be resolved to an integer
+/// - The selected form cannot be resolved
+pub struct PluralDirective {
+    /// The count selecting the form
+    pub count: Argument,
+    /// The forms with the counts they apply to
+    pub forms: Vec<(PluralSelector, Argument)>,
+}
+
+impl Directive for PluralDirective {
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        match self.value(scope)? {
+            Value::Str(text) | Value::Safe(text) => Ok(text),
+            _ => Ok(Cow::Borrowed("")),
+        }
+    }
+
+    fn value(&self, scope: &Scope) -> Result<Value, DirectiveError> {
+        let count = self.count.resolve_as::<i64>(scope)?;
+        let category = scope.env().locale().plural.category(count);
+
+        let form = self
+            .forms
+            .iter()
+            .find(|(selector, _)| *selector == PluralSelector::Exact(count))
+            .or_else(|| {
+                self.forms
+                    .iter()
+                    .find(|(selector, _)| *selector == PluralSelector::Category(category))
+            })
+            .or_else(|| {
+                self.forms.iter().find(|(selector, _)| {
+                    *selector == PluralSelector::Category(PluralCategory::Other)
+                })
+            });
+
+        let Some((_, form)) = form else {
+            return Ok(Value::static_str(""));
+        };
+
+        let value = form.resolve_output(scope)?;
+
+        Ok(match value {
+            Value::Safe(text) if text.contains('#') => {
+                Value::Safe(Cow::Owned(text.replace('#', &count.to_astring())))
+            }
+            Value::Safe(text) => Value::Safe(text),
+            value => {
+                let text = Cow::<'static, str>::from_value(&value).unwrap_or_default();
+                Value::Str(Cow::Owned(text.replace('#', &count.to_astring())))
+            }
+        })
+    }
+}
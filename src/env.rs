@@ -0,0 +1,641 @@
+//! Render-time environment and the scope directives are executed in.
+//!
+//! An [`Environment`] holds everything that is shared across renders but is not
+//! part of the template itself, such as the functions templates can call, how
+//! to handle missing variables, the locale numbers are written in, how
+//! output is escaped and how much work a render may do.
+//! While rendering, directives receive a [`Scope`] that bundles the caller's
+//! [`Context`], or a [`LayeredContext`] of several, with the environment.
+
+use std::{borrow::Cow, cell::Cell, collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    Context, Value,
+    collect::Collector,
+    err::DirectiveError,
+    escape::Escape,
+    func::{Function, IntoFunction, Variadic},
+    limits::{Limit, Limits},
+    loader::IncludeResolver,
+    locale::Locale,
+    macros::{Macro, Macros},
+};
+
+/// Functions and settings shared by every render that uses it.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, Template, Value};
+///
+/// let mut env = Environment::new();
+/// env.register("max", |a: i64, b: i64| a.max(b));
+///
+/// let tmpl = Template::<'{', '}'>::compile("{max(a, b)}").unwrap();
+///
+/// let mut ctx = Context::new();
+/// ctx.insert("a", Value::Int(3));
+/// ctx.insert("b", Value::Int(7));
+///
+/// assert_eq!(tmpl.format_with(&ctx, &env).unwrap(), "7");
+/// ```
+#[derive(Default, Clone)]
+pub struct Environment {
+    functions: HashMap<Cow<'static, str>, Arc<dyn Function>>,
+    missing_variable: MissingVariable,
+    locale: Locale,
+    escape: Escape,
+    limits: Limits,
+    error_marker: Cow<'static, str>,
+}
+
+/// What a directive renders when it refers to a variable missing from the context.
+///
+/// The policy applies to the whole directive: `{user.name}` and
+/// `{missing ? 'a' : 'b'}` are both replaced when a variable they use is
+/// missing.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, MissingVariable, Template};
+///
+/// let tmpl = Template::<'{', '}'>::compile("Hello {name}!").unwrap();
+///
+/// let mut env = Environment::new();
+/// env.set_missing_variable(MissingVariable::Original);
+///
+/// assert_eq!(tmpl.format_with(&Context::new(), &env).unwrap(), "Hello {name}!");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MissingVariable {
+    /// Fail the render with `DirectiveError::NotFound`
+    #[default]
+    Error,
+    /// Render nothing
+    Empty,
+    /// Render the directive as written in the template, delimiters included.
+    /// A repeat block whose count or separator is missing renders whole,
+    /// from `{repeat ...}` to `{endrepeat}`. A failed `{set}` renders
+    /// nothing, like any assignment
+    Original,
+    /// Render the given text
+    Placeholder(Cow<'static, str>),
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Environment")
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("missing_variable", &self.missing_variable)
+            .field("locale", &self.locale)
+            .field("escape", &self.escape)
+            .field("limits", &self.limits)
+            .field("error_marker", &self.error_marker)
+            .finish()
+    }
+}
+
+impl Environment {
+    /// Creates an empty environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a typed closure as a template function.
+    ///
+    /// The closure may take up to four arguments of any [`Resolvable`](crate::Resolvable)
+    /// type. Calls with the wrong number of arguments fail with
+    /// `DirectiveError::ArityMismatch`, and arguments that cannot be converted fail
+    /// with `DirectiveError::FunctionArgument`. Registering a name twice replaces
+    /// the previous function.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name used to call the function from templates
+    /// * `func` - The closure to call
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::Environment;
+    ///
+    /// let mut env = Environment::new();
+    /// env.register("double", |x: f64| x * 2.0);
+    /// env.register("shout", |s: std::borrow::Cow<'static, str>| s.to_uppercase());
+    /// ```
+    pub fn register<Args, F: IntoFunction<Args>>(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        func: F,
+    ) {
+        self.functions
+            .insert(name.into(), Arc::from(func.into_function()));
+    }
+
+    /// Registers a closure receiving the raw argument list.
+    ///
+    /// Use this for functions with optional or variable arguments; the closure is
+    /// responsible for validating its arguments.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Environment, Value};
+    ///
+    /// let mut env = Environment::new();
+    /// env.register_variadic("count", |args| Ok(Value::Int(args.len() as i64)));
+    /// ```
+    pub fn register_variadic<F>(&mut self, name: impl Into<Cow<'static, str>>, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, DirectiveError> + Send + Sync + 'static,
+    {
+        self.functions.insert(name.into(), Arc::new(Variadic(func)));
+    }
+
+    /// Registers a custom [`Function`] implementation.
+    pub fn register_function(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        func: impl Function + 'static,
+    ) {
+        self.functions.insert(name.into(), Arc::new(func));
+    }
+
+    /// Looks up a registered function by name.
+    pub fn function(&self, name: &str) -> Option<&dyn Function> {
+        self.functions.get(name).map(|f| f.as_ref())
+    }
+
+    /// Sets what directives render when a variable is missing.
+    ///
+    /// Defaults to [`MissingVariable::Error`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Environment, MissingVariable};
+    ///
+    /// let mut env = Environment::new();
+    /// env.set_missing_variable(MissingVariable::Placeholder("???".into()));
+    /// ```
+    pub fn set_missing_variable(&mut self, policy: MissingVariable) {
+        self.missing_variable = policy;
+    }
+
+    /// Returns the policy for missing variables.
+    pub fn missing_variable(&self) -> &MissingVariable {
+        &self.missing_variable
+    }
+
+    /// Sets the locale used by format specs to write numbers.
+    ///
+    /// Defaults to [`Locale::EN_US`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Environment, Locale};
+    ///
+    /// let mut env = Environment::new();
+    /// env.set_locale(Locale::from_tag("de-DE").unwrap());
+    /// ```
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.locale = locale;
+    }
+
+    /// Returns the locale used by format specs.
+    pub fn locale(&self) -> &Locale {
+        &self.locale
+    }
+
+    /// Sets how the output of directives is escaped.
+    ///
+    /// Defaults to [`Escape::None`]. A mode set on the template with
+    /// [`Template::set_escape`](crate::Template::set_escape) takes precedence.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Environment, Escape};
+    ///
+    /// let mut env = Environment::new();
+    /// env.set_escape(Escape::Html);
+    /// ```
+    pub fn set_escape(&mut self, escape: Escape) {
+        self.escape = escape;
+    }
+
+    /// Returns how the output of directives is escaped.
+    pub fn escape(&self) -> Escape {
+        self.escape
+    }
+
+    /// Sets the limits renders must stay within.
+    ///
+    /// Defaults to [`Limits::default`], which bounds output size, repetitions
+    /// and nesting depth.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Environment, Limits};
+    ///
+    /// let mut env = Environment::new();
+    /// env.set_limits(Limits {
+    ///     max_output: Some(64 * 1024),
+    ///     fuel: Some(10_000),
+    ///     ..Limits::default()
+    /// });
+    /// ```
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Returns the limits renders must stay within.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Sets what [`Template::format_collect`](crate::Template::format_collect)
+    /// renders in place of a directive that fails.
+    ///
+    /// Defaults to an empty string. The marker is not escaped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::Environment;
+    ///
+    /// let mut env = Environment::new();
+    /// env.set_error_marker("<span class=\"error\">!</span>");
+    /// ```
+    pub fn set_error_marker(&mut self, marker: impl Into<Cow<'static, str>>) {
+        self.error_marker = marker.into();
+    }
+
+    /// Returns what failing directives render as when errors are collected.
+    pub fn error_marker(&self) -> &str {
+        &self.error_marker
+    }
+}
+
+/// Variables bound while rendering, such as macro arguments, repeat indices
+/// or variables assigned with `{set name = ...}`.
+///
+/// Locals are layered over the context with [`LayeredContext::with_locals`].
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, LayeredContext, Locals, Scope, Value};
+///
+/// let mut ctx = Context::new();
+/// ctx.insert("name", Value::static_str("Alice"));
+///
+/// let env = Environment::new();
+/// let root = LayeredContext::new(&ctx);
+/// let locals = Locals::new(vec![("name".into(), Value::static_str("Bob"))]);
+/// let scope = Scope::new(&ctx, &env).with_variables(root.with_locals(&locals));
+///
+/// assert!(matches!(scope.get("name"), Some(Value::Str(name)) if name == "Bob"));
+/// ```
+#[derive(Debug, Default)]
+pub struct Locals {
+    vars: Vec<(Cow<'static, str>, Value)>,
+}
+
+impl Locals {
+    /// Creates a frame with the given variables.
+    pub fn new(vars: Vec<(Cow<'static, str>, Value)>) -> Self {
+        Self { vars }
+    }
+
+    /// Adds a variable to this frame, shadowing any earlier one with the same name.
+    pub(crate) fn bind(&mut self, name: Cow<'static, str>, value: Value) {
+        self.vars.push((name, value));
+    }
+
+    /// Looks up a variable in this frame.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.vars
+            .iter()
+            .rev()
+            .find(|(var, _)| var == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// A context chained over a parent context.
+///
+/// Lookups check the child's variables before its parent's, so values shared by
+/// every render, such as a company name, can live in one long-lived context
+/// while each render only builds the values specific to it. Neither context is
+/// copied. Layers can be stacked to any depth. Variables bound while
+/// rendering, such as macro arguments, are [`Locals`] layered on top the same
+/// way, so every lookup of a render goes through one chain.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, LayeredContext, Template, Value};
+///
+/// let mut globals = Context::new();
+/// globals.insert("company", Value::static_str("Acme"));
+/// globals.insert("name", Value::static_str("customer"));
+/// let globals = LayeredContext::new(&globals);
+///
+/// let mut request = Context::new();
+/// request.insert("name", Value::static_str("Alice"));
+///
+/// let tmpl = Template::<'{', '}'>::compile("{company} welcomes {name}").unwrap();
+/// let output = tmpl
+///     .format_layered(&globals.child(&request), &Environment::new())
+///     .unwrap();
+///
+/// assert_eq!(output, "Acme welcomes Alice");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LayeredContext<'a> {
+    layer: Layer<'a>,
+    parent: Option<&'a Self>,
+}
+
+/// The variables of one layer of a [`LayeredContext`].
+#[derive(Debug, Clone, Copy)]
+enum Layer<'a> {
+    /// A context supplied by the caller
+    Context(&'a Context),
+    /// Variables bound while rendering
+    Locals(&'a Locals),
+}
+
+impl<'a> LayeredContext<'a> {
+    /// Creates a context with a single layer.
+    pub fn new(layer: &'a Context) -> Self {
+        Self {
+            layer: Layer::Context(layer),
+            parent: None,
+        }
+    }
+
+    /// Creates a context whose lookups check `layer` first and fall back to
+    /// this one.
+    pub fn child(&'a self, layer: &'a Context) -> Self {
+        Self {
+            layer: Layer::Context(layer),
+            parent: Some(self),
+        }
+    }
+
+    /// Creates a context whose lookups check `locals` first and fall back to
+    /// this one.
+    pub fn with_locals(&'a self, locals: &'a Locals) -> Self {
+        Self {
+            layer: Layer::Locals(locals),
+            parent: Some(self),
+        }
+    }
+
+    /// Returns the innermost layer, or `None` if it holds locals.
+    pub fn layer(&self) -> Option<&'a Context> {
+        match self.layer {
+            Layer::Context(ctx) => Some(ctx),
+            Layer::Locals(_) => None,
+        }
+    }
+
+    /// Returns the context this one falls back to, if any.
+    pub fn parent(&self) -> Option<&'a Self> {
+        self.parent
+    }
+
+    /// Looks up a variable in this layer and its parents.
+    pub fn get(&self, name: &str) -> Option<&'a Value> {
+        match self.layer {
+            Layer::Context(ctx) => ctx.get(name),
+            Layer::Locals(locals) => locals.get(name),
+        }
+        .or_else(|| self.parent?.get(name))
+    }
+
+    /// Returns this context without the locals layered on top of it.
+    fn without_locals(&self) -> Self {
+        match (self.layer, self.parent) {
+            (Layer::Locals(_), Some(parent)) => parent.without_locals(),
+            _ => *self,
+        }
+    }
+}
+
+/// Everything a directive can access while a template is rendered.
+///
+/// A scope pairs the caller's [`Context`] with the [`Environment`] used for the
+/// render. Directives look variables up through the scope rather than the
+/// context directly, so [`Locals`] such as macro arguments shadow the context.
+/// The context and the locals form a single [`LayeredContext`].
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, Scope, Value};
+///
+/// let mut ctx = Context::new();
+/// ctx.insert("name", Value::static_str("Alice"));
+///
+/// let env = Environment::new();
+/// let scope = Scope::new(&ctx, &env);
+/// assert!(scope.get("name").is_some());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Scope<'a> {
+    vars: LayeredContext<'a>,
+    env: &'a Environment,
+    loader: Option<&'a dyn IncludeResolver>,
+    macros: Option<&'a Macros>,
+    escape: Escape,
+    /// Number of macro calls and includes being rendered
+    depth: usize,
+    /// Remaining fuel, shared by everything rendered with this scope
+    fuel: Option<&'a Cell<u64>>,
+    /// Where failing directives are recorded when errors are collected
+    collector: Option<&'a Collector>,
+}
+
+impl<'a> Scope<'a> {
+    /// Creates a scope over a context and an environment.
+    pub fn new(ctx: &'a Context, env: &'a Environment) -> Self {
+        Self::layered(&LayeredContext::new(ctx), env)
+    }
+
+    /// Creates a scope over a layered context and an environment.
+    pub fn layered(ctx: &LayeredContext<'a>, env: &'a Environment) -> Self {
+        Self {
+            vars: *ctx,
+            env,
+            loader: None,
+            macros: None,
+            escape: env.escape(),
+            depth: 0,
+            fuel: None,
+            collector: None,
+        }
+    }
+
+    /// Returns this scope with a resolver for `{> name}` includes.
+    ///
+    /// Set by [`Loader::render`](crate::Loader::render); scopes without a
+    /// resolver fail on includes.
+    pub fn with_loader(self, loader: &'a dyn IncludeResolver) -> Self {
+        Self {
+            loader: Some(loader),
+            ..self
+        }
+    }
+
+    /// Returns this scope looking variables up in `vars`.
+    ///
+    /// Layer [`Locals`] over [`Scope::variables`] to bind variables for
+    /// nested rendering, or over [`Scope::context`] to hide the locals
+    /// already bound.
+    pub fn with_variables(self, vars: LayeredContext<'a>) -> Self {
+        Self { vars, ..self }
+    }
+
+    /// Returns this scope with the escaping mode used for directive output.
+    ///
+    /// Starts as the environment's mode; templates with their own mode set
+    /// it while they render.
+    pub fn with_escape(self, escape: Escape) -> Self {
+        Self { escape, ..self }
+    }
+
+    /// Returns this scope with the macros of the template being rendered.
+    pub(crate) fn with_macros(self, macros: &'a Macros) -> Self {
+        Self {
+            macros: Some(macros),
+            ..self
+        }
+    }
+
+    /// Looks up a variable by name, in the locals first and then the context,
+    /// from its innermost layer outwards.
+    pub fn get(&self, name: &str) -> Option<&'a Value> {
+        self.vars.get(name)
+    }
+
+    /// Returns every variable the scope sees, locals included.
+    pub fn variables(&self) -> LayeredContext<'a> {
+        self.vars
+    }
+
+    /// Returns the caller's context, without the locals bound while rendering.
+    pub fn context(&self) -> LayeredContext<'a> {
+        self.vars.without_locals()
+    }
+
+    /// Returns the environment used for this render.
+    pub fn env(&self) -> &'a Environment {
+        self.env
+    }
+
+    /// Returns the resolver for includes, if any.
+    pub fn loader(&self) -> Option<&'a dyn IncludeResolver> {
+        self.loader
+    }
+
+    /// Returns how directive output is escaped.
+    pub fn escape(&self) -> Escape {
+        self.escape
+    }
+
+    /// Returns this scope recording failing directives instead of stopping.
+    pub(crate) fn with_collector(self, collector: &'a Collector) -> Self {
+        Self {
+            collector: Some(collector),
+            ..self
+        }
+    }
+
+    /// Returns where failing directives are recorded, if errors are collected.
+    pub(crate) fn collector(&self) -> Option<&'a Collector> {
+        self.collector
+    }
+
+    /// Looks up a macro of the template being rendered.
+    pub(crate) fn macro_named(&self, name: &str) -> Option<&'a Macro> {
+        self.macros?.get(name).map(|m| &**m)
+    }
+
+    /// Runs `render` with the fuel allowed by the environment's limits, unless
+    /// this scope already draws from a render's fuel.
+    pub(crate) fn fueled<R>(&self, render: impl FnOnce(&Scope) -> R) -> R {
+        match (self.fuel, self.env.limits.fuel) {
+            (None, Some(fuel)) => {
+                let fuel = Cell::new(fuel);
+                render(&Scope {
+                    fuel: Some(&fuel),
+                    ..*self
+                })
+            }
+            _ => render(self),
+        }
+    }
+
+    /// Uses one unit of fuel.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DirectiveError::LimitExceeded` if the fuel is spent.
+    pub(crate) fn use_fuel(&self) -> Result<(), DirectiveError> {
+        match self.fuel {
+            Some(fuel) if fuel.get() == 0 => Err(DirectiveError::LimitExceeded {
+                limit: Limit::Fuel,
+                max: self.env.limits.fuel.unwrap_or_default(),
+            }),
+            Some(fuel) => {
+                fuel.set(fuel.get() - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns this scope one macro call or include deeper.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DirectiveError::LimitExceeded` if the nesting gets too deep.
+    pub(crate) fn nested(self) -> Result<Self, DirectiveError> {
+        let depth = self.depth + 1;
+
+        Self::check(Limit::Depth, self.env.limits.max_depth, depth)?;
+
+        Ok(Self { depth, ..self })
+    }
+
+    /// Checks that `len` bytes of output are allowed.
+    ///
+    /// Without an output limit, the output is still bounded by the largest
+    /// possible `String`.
+    pub(crate) fn check_output(&self, len: usize) -> Result<(), DirectiveError> {
+        let max = self.env.limits.max_output.unwrap_or(isize::MAX as usize);
+
+        Self::check(Limit::Output, Some(max.min(isize::MAX as usize)), len)
+    }
+
+    /// Checks that repeating something `count` times is allowed.
+    pub(crate) fn check_repeat(&self, count: usize) -> Result<(), DirectiveError> {
+        Self::check(Limit::Repeat, self.env.limits.max_repeat, count)
+    }
+
+    fn check(limit: Limit, max: Option<usize>, value: usize) -> Result<(), DirectiveError> {
+        match max {
+            Some(max) if value > max => Err(DirectiveError::LimitExceeded {
+                limit,
+                max: max as u64,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -3,8 +3,12 @@
 //! This module defines the errors that can occur during template rendering,
 //! particularly when resolving arguments and executing directives.
 
+use std::{borrow::Cow, fmt};
+
 use thiserror::Error;
 
+use crate::{lexer::Span, limits::Limit};
+
 /// Errors that can occur during directive execution.
 ///
 /// These errors represent runtime failures when rendering a compiled template,
@@ -14,20 +18,19 @@ use thiserror::Error;
 /// # Examples
 ///
 /// ```rust
-/// use figura::{Template, DefaultParser, Context, Value, DirectiveError};
-/// use std::collections::HashMap;
+/// use figura::{Template, Context, DirectiveError};
 ///
-/// let tmpl = Template::<'{', '}'>::compile::<DefaultParser>("{missing}").unwrap();
-/// let ctx = HashMap::new();
+/// let tmpl = Template::<'{', '}'>::compile("{missing}").unwrap();
+/// let ctx = Context::new();
 ///
-/// match tmpl.format(&ctx) {
+/// match tmpl.format(&ctx).map_err(DirectiveError::into_root) {
 ///     Err(DirectiveError::NotFound { name, .. }) => {
 ///         println!("Variable '{}' not found", name);
 ///     }
 ///     _ => {}
 /// }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[derive(Error)]
 pub enum DirectiveError {
     /// A variable was not found in the template context.
@@ -62,7 +65,7 @@ pub enum DirectiveError {
     ///
     /// # Fields
     ///
-    /// * `name` - The name of the variable with the wrong type
+    /// * `name` - The name of the variable, or the expression, with the wrong type
     /// * `expected` - The type that was expected (e.g., "i64")
     /// * `found` - The actual type of the variable (e.g., "string")
     ///
@@ -104,6 +107,355 @@ pub enum DirectiveError {
         type_name: &'static str,
         message: String,
     },
+
+    /// A template called a function that is not registered on the environment.
+    ///
+    /// # Fields
+    ///
+    /// * `name` - The name of the unknown function
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{shout(name)}"
+    /// Environment: (no functions)
+    /// Error: Function 'shout' is not registered in the environment
+    /// ```
+    #[error("Function '{name}' is not registered in the environment")]
+    UnknownFunction { name: String },
+
+    /// A function was called with the wrong number of arguments.
+    ///
+    /// # Fields
+    ///
+    /// * `function` - The name of the called function
+    /// * `expected` - The number of arguments the function takes
+    /// * `found` - The number of arguments it was called with
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{max(a)}"  (max takes two arguments)
+    /// Error: Function 'max' takes 2 argument(s) but was called with 1
+    /// ```
+    #[error("Function '{function}' takes {expected} argument(s) but was called with {found}")]
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+
+    /// A function argument could not be converted to the type the function expects.
+    ///
+    /// # Fields
+    ///
+    /// * `function` - The name of the called function
+    /// * `position` - The 1-based position of the argument
+    /// * `expected` - The type the function expects
+    /// * `found` - The actual type of the argument
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{max(a, 'x')}"  (max takes two integers)
+    /// Error: Argument 2 of function 'max' has type 'string' but was expected to have type 'i64'
+    /// ```
+    #[error(
+        "Argument {position} of function '{function}' has type '{found}' but was expected to have type '{expected}'"
+    )]
+    FunctionArgument {
+        function: String,
+        position: usize,
+        expected: &'static str,
+        found: String,
+    },
+
+    /// Two values of types that have no defined ordering were compared.
+    ///
+    /// Numbers compare with numbers, strings with strings and booleans with
+    /// booleans; any other pairing is rejected instead of falling back to a
+    /// string comparison.
+    ///
+    /// # Fields
+    ///
+    /// * `left` - The type of the left-hand side
+    /// * `op` - The comparison operator
+    /// * `right` - The type of the right-hand side
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{count > 'many' ? a : b}"  (count is an integer)
+    /// Error: Cannot compare 'integer' with 'string' using '>'
+    /// ```
+    #[error("Cannot compare '{left}' with '{right}' using '{op}'")]
+    Incomparable {
+        left: String,
+        op: &'static str,
+        right: String,
+    },
+
+    /// An arithmetic operator was applied to values it does not support.
+    ///
+    /// Numbers combine with numbers, and `+` also joins two strings. Strings
+    /// are not converted to numbers.
+    ///
+    /// # Fields
+    ///
+    /// * `left` - The type of the left-hand side
+    /// * `op` - The arithmetic operator
+    /// * `right` - The type of the right-hand side
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{price * 'two'}"  (price is a float)
+    /// Error: Cannot apply '*' to 'float' and 'string'
+    /// ```
+    #[error("Cannot apply '{op}' to '{left}' and '{right}'")]
+    InvalidOperands {
+        left: String,
+        op: &'static str,
+        right: String,
+    },
+
+    /// A number was divided by zero.
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    /// Integer arithmetic overflowed.
+    ///
+    /// # Fields
+    ///
+    /// * `op` - The arithmetic operator
+    #[error("Integer overflow in '{op}'")]
+    Overflow { op: &'static str },
+
+    /// The render exceeded one of the environment's [`Limits`](crate::Limits).
+    ///
+    /// # Fields
+    ///
+    /// * `limit` - Which limit was exceeded
+    /// * `max` - The configured maximum
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{'x':1000000}"  (max_repeat is 100)
+    /// Error: Render limit exceeded: repeat count is limited to 100
+    /// ```
+    #[error("Render limit exceeded: {limit} is limited to {max}")]
+    LimitExceeded { limit: Limit, max: u64 },
+
+    /// An included template could not be loaded.
+    ///
+    /// # Fields
+    ///
+    /// * `name` - The name of the included template
+    /// * `reason` - Why it could not be loaded
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "{> header}"  (rendered without a Loader)
+    /// Error: Cannot include template 'header': no loader is available
+    /// ```
+    #[error("Cannot include template '{name}': {reason}")]
+    Include { name: String, reason: String },
+
+    /// A template extending another was rendered without being resolved.
+    ///
+    /// Templates using `{extends name}` must be loaded through a
+    /// [`Loader`](crate::Loader), which merges them with their parent.
+    ///
+    /// # Fields
+    ///
+    /// * `name` - The name of the parent template
+    #[error("Template extends '{name}' and must be loaded through a Loader")]
+    UnresolvedExtends { name: String },
+
+    /// A directive failed; wraps its error with where the directive is.
+    ///
+    /// Every error raised by a parsed directive while rendering is wrapped
+    /// once, by the innermost directive, so an error from a macro body points
+    /// into the macro rather than at the call. Use [`DirectiveError::root`]
+    /// to match on the underlying error.
+    ///
+    /// # Fields
+    ///
+    /// * `location` - The failing directive and where it is
+    /// * `source` - The error it raised
+    ///
+    /// # Examples
+    ///
+    /// ```text
+    /// Template: "Hi {name}!"  (loaded as 'greeting')
+    /// Error: {name} at 4..8 in template 'greeting': Variable 'name' was not found in the context while being used as 'string'
+    /// ```
+    #[error("{location}: {source}")]
+    At {
+        location: Location,
+        #[source]
+        source: Box<Self>,
+    },
+}
+
+impl DirectiveError {
+    /// Returns the error without its location.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Context, DirectiveError, Template};
+    ///
+    /// let tmpl = Template::<'{', '}'>::compile("Hi {name}!").unwrap();
+    /// let err = tmpl.format(&Context::new()).unwrap_err();
+    ///
+    /// assert!(matches!(err.root(), DirectiveError::NotFound { .. }));
+    /// ```
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::At { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// Consumes the error and returns it without its location.
+    pub fn into_root(self) -> Self {
+        match self {
+            Self::At { source, .. } => source.into_root(),
+            err => err,
+        }
+    }
+
+    /// Returns where the failing directive is, if the error is tied to one.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Self::At { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+}
+
+/// Where a directive is in the template it was compiled from.
+///
+/// Displayed as `{name} at 4..8 in template 'greeting'`, the template name
+/// being known only for templates loaded through a [`Loader`](crate::Loader).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The name of the template, if it was loaded through a loader
+    pub template: Option<String>,
+    /// Where the directive's content is in the template
+    pub span: Span,
+    /// The directive as written, delimiters included
+    pub directive: String,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.directive, self.span)?;
+
+        if let Some(template) = &self.template {
+            write!(f, " in template '{}'", template)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error reported by a [`Parser`](crate::Parser) when a directive is invalid.
+///
+/// Carries a human-readable reason and, when known, the index of the offending
+/// token in the slice handed to the parser.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::SyntaxError;
+///
+/// let err = SyntaxError::at(2, "expected a variable or an integer");
+/// assert_eq!(err.token, Some(2));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message}")]
+pub struct SyntaxError {
+    /// Why the directive could not be parsed.
+    pub message: Cow<'static, str>,
+    /// Index of the offending token, if the error can be pinned to one.
+    pub token: Option<usize>,
+    /// Whether the parser rejected the directive or simply did not recognize it.
+    pub kind: SyntaxErrorKind,
+}
+
+/// The category of a [`SyntaxError`].
+///
+/// Chained parsers (see [`Parser::or`](crate::Parser::or)) only fall back to the
+/// next parser on [`SyntaxErrorKind::Unrecognized`] errors, so a directive that a
+/// parser recognizes but finds malformed is reported instead of being handed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxErrorKind {
+    /// The directive matches one of the parser's rules but is malformed.
+    Invalid,
+    /// The directive matches none of the parser's rules; another parser may handle it.
+    Unrecognized,
+}
+
+impl SyntaxError {
+    /// Creates an error that is not tied to a specific token.
+    pub fn new(message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            message: message.into(),
+            token: None,
+            kind: SyntaxErrorKind::Invalid,
+        }
+    }
+
+    /// Creates an error pointing at the token with the given index.
+    pub fn at(token: usize, message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            message: message.into(),
+            token: Some(token),
+            kind: SyntaxErrorKind::Invalid,
+        }
+    }
+
+    /// Creates an error signaling that the parser does not handle this directive.
+    pub fn unrecognized() -> Self {
+        Self {
+            message: Cow::Borrowed("unrecognized directive"),
+            token: None,
+            kind: SyntaxErrorKind::Unrecognized,
+        }
+    }
+
+    /// Returns `true` if the parser did not recognize the directive at all.
+    pub fn is_unrecognized(&self) -> bool {
+        self.kind == SyntaxErrorKind::Unrecognized
+    }
+}
+
+/// A problem found while tokenizing a directive.
+///
+/// Reported by [`TemplateLexer::spanned`](crate::TemplateLexer::spanned), with the
+/// location of the offending input.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LexError {
+    /// A character that does not start any token.
+    #[error("Unexpected character '{ch}' at {span}")]
+    UnknownCharacter { ch: char, span: Span },
+
+    /// A string literal missing its closing quote. The span starts at the opening quote.
+    #[error("Unterminated string literal at {span}")]
+    UnterminatedLiteral { span: Span },
+}
+
+impl LexError {
+    /// Returns the location of the offending input.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnknownCharacter { span, .. } | Self::UnterminatedLiteral { span } => *span,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -111,6 +463,42 @@ pub enum TemplateError {
     #[error("Unclosed delimiter '{0}'")]
     MissingDelimiter(char),
 
-    #[error("Failed to parse directive: {0}")]
-    DirectiveParsing(String),
+    /// The parser rejected a directive.
+    ///
+    /// # Fields
+    ///
+    /// * `content` - The raw text between the delimiters
+    /// * `error` - The reason given by the parser
+    /// * `span` - Where in the template the error is: the offending token if the
+    ///   parser named one, the whole directive content otherwise
+    #[error("Failed to parse directive '{content}': {error}")]
+    DirectiveParsing {
+        content: String,
+        error: SyntaxError,
+        span: Span,
+    },
+
+    #[error("Unclosed raw block, missing 'endraw' tag")]
+    UnclosedRawBlock,
+
+    /// A `{block name}` is missing its `{endblock}`.
+    #[error("Unclosed block '{name}', missing 'endblock' tag")]
+    UnclosedBlock { name: String },
+
+    /// A `{macro name(...)}` is missing its `{endmacro}`.
+    #[error("Unclosed macro '{name}', missing 'endmacro' tag")]
+    UnclosedMacro { name: String },
+
+    /// A `{repeat ...}` is missing its `{endrepeat}`.
+    #[error("Unclosed repeat, missing 'endrepeat' tag")]
+    UnclosedRepeat,
+
+    /// A string literal inside a directive is missing its closing quote.
+    ///
+    /// # Fields
+    ///
+    /// * `content` - The raw text between the delimiters
+    /// * `span` - Where in the template the literal starts and ends
+    #[error("Unterminated string literal in directive '{content}' at {span}")]
+    UnterminatedLiteral { content: String, span: Span },
 }
@@ -0,0 +1,122 @@
+//! Escaping of substituted values.
+//!
+//! Templates producing HTML, JSON, shell commands or CSV must not insert values
+//! verbatim. An [`Escape`] mode, set on the [`Template`](crate::Template) or the
+//! [`Environment`](crate::Environment), is applied to the output of every
+//! directive, while the text written in the template itself is left alone.
+//! Values wrapped in [`Value::Safe`](crate::Value::Safe) are trusted and never
+//! escaped.
+
+use std::borrow::Cow;
+
+/// How substituted values are escaped.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::Escape;
+///
+/// assert_eq!(Escape::Html.escape("<b>Tom & Jerry</b>"), "&lt;b&gt;Tom &amp; Jerry&lt;/b&gt;");
+/// assert_eq!(Escape::Json.escape("say \"hi\"\n"), "say \\\"hi\\\"\\n");
+/// assert_eq!(Escape::Shell.escape("it's"), "'it'\\''s'");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Escape {
+    /// Values are inserted verbatim
+    #[default]
+    None,
+    /// HTML text and quoted attributes: `&`, `<`, `>`, `"` and `'`
+    Html,
+    /// XML attribute values: like `Html`, plus tabs and line breaks so they
+    /// survive attribute normalization
+    XmlAttribute,
+    /// The inside of a JSON string: quotes, backslashes and control characters
+    Json,
+    /// A single shell word: the value is wrapped in single quotes
+    Shell,
+    /// A CSV field: quoted when it contains a comma, quote or line break
+    Csv,
+}
+
+impl Escape {
+    /// Escapes a value for this mode.
+    ///
+    /// Returns the input unchanged when nothing needs escaping, except in
+    /// `Shell` mode, which always quotes.
+    pub fn escape<'a>(self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::None => Cow::Borrowed(text),
+            Self::Html => Self::replace(text, |ch| match ch {
+                '&' => Some("&amp;"),
+                '<' => Some("&lt;"),
+                '>' => Some("&gt;"),
+                '"' => Some("&quot;"),
+                '\'' => Some("&#39;"),
+                _ => None,
+            }),
+            Self::XmlAttribute => Self::replace(text, |ch| match ch {
+                '&' => Some("&amp;"),
+                '<' => Some("&lt;"),
+                '>' => Some("&gt;"),
+                '"' => Some("&quot;"),
+                '\'' => Some("&apos;"),
+                '\t' => Some("&#9;"),
+                '\n' => Some("&#10;"),
+                '\r' => Some("&#13;"),
+                _ => None,
+            }),
+            Self::Json => Self::json(text),
+            Self::Shell => Cow::Owned(format!("'{}'", text.replace('\'', "'\\''"))),
+            Self::Csv => {
+                if text.contains([',', '"', '\n', '\r']) {
+                    Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+                } else {
+                    Cow::Borrowed(text)
+                }
+            }
+        }
+    }
+
+    /// Replaces the characters `map` returns an entity for.
+    fn replace(text: &str, map: impl Fn(char) -> Option<&'static str>) -> Cow<'_, str> {
+        let Some(first) = text.find(|ch| map(ch).is_some()) else {
+            return Cow::Borrowed(text);
+        };
+
+        let mut out = String::with_capacity(text.len() + 16);
+        out.push_str(&text[..first]);
+
+        for ch in text[first..].chars() {
+            match map(ch) {
+                Some(entity) => out.push_str(entity),
+                None => out.push(ch),
+            }
+        }
+
+        Cow::Owned(out)
+    }
+
+    fn json(text: &str) -> Cow<'_, str> {
+        if !text.contains(|ch: char| ch == '"' || ch == '\\' || ch.is_control()) {
+            return Cow::Borrowed(text);
+        }
+
+        let mut out = String::with_capacity(text.len() + 16);
+
+        for ch in text.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\u{8}' => out.push_str("\\b"),
+                '\u{c}' => out.push_str("\\f"),
+                ch if ch.is_control() => out.push_str(&format!("\\u{:04x}", ch as u32)),
+                ch => out.push(ch),
+            }
+        }
+
+        Cow::Owned(out)
+    }
+}
@@ -0,0 +1,388 @@
+//! Format specifications for substitutions.
+//!
+//! A directive can be followed by `|` and a spec modelled on `std::fmt`:
+//! `{price | >10.2}`, `{name | *^20}`, `{delta | +08.3}`. The spec controls the
+//! fill character, alignment, width, precision, sign and zero padding of the
+//! rendered value. Width and precision can also name a variable: `{name | <w}`.
+//! Numbers can be grouped and shown as currency or percent following the
+//! environment's [`Locale`](crate::Locale): `{revenue | ,.2$}`.
+
+use std::borrow::Cow;
+
+use crate::{
+    Value,
+    arg::{Argument, Resolvable},
+    env::Scope,
+    err::{DirectiveError, SyntaxError},
+    traits::ToAstring,
+};
+
+/// How a value is aligned within its width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// `<`
+    Left,
+    /// `^`
+    Center,
+    /// `>`
+    Right,
+}
+
+/// How a number is presented, selected by the last character of a spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberStyle {
+    /// The number itself
+    #[default]
+    Plain,
+    /// `$`: an amount with the locale's currency symbol, two decimals by default
+    Currency,
+    /// `%`: the number multiplied by 100 with the locale's percent sign
+    Percent,
+}
+
+/// A width or precision, either written in the spec or read from a variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Count {
+    /// A number written in the spec, e.g. `10`
+    Fixed(usize),
+    /// A variable holding the number, e.g. `col_width`
+    Variable(Cow<'static, str>),
+}
+
+impl Count {
+    fn resolve(&self, scope: &Scope) -> Result<usize, DirectiveError> {
+        match self {
+            Self::Fixed(n) => Ok(*n),
+            Self::Variable(name) => {
+                let n = Argument::variable(name.clone()).resolve_as::<i64>(scope)?;
+
+                usize::try_from(n).map_err(|_| DirectiveError::TypeError {
+                    name: name.to_string(),
+                    expected: "non-negative i64",
+                    found: n.to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// A parsed format specification.
+///
+/// The grammar follows `std::fmt`:
+///
+/// ```text
+/// spec      := [[fill]align][sign]['0'][width][','][.precision][style]
+/// fill      := any character
+/// align     := '<' | '^' | '>'
+/// sign      := '+'
+/// width     := count
+/// precision := count
+/// count     := integer | identifier
+/// style     := '$' | '%'
+/// ```
+///
+/// Numbers are right-aligned and everything else left-aligned unless an
+/// alignment is given. Precision sets the number of decimals of numbers,
+/// padding integers with zeros (`3` → `3.00`), and truncates strings.
+/// `0` pads numbers with zeros after the sign and is ignored for other values.
+/// `,` groups the digits of numbers by thousands. Decimal and group
+/// separators, currency and percent signs come from the environment's
+/// [`Locale`](crate::Locale).
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Align, Count, FormatSpec};
+///
+/// let spec: FormatSpec = "*^10.2".parse().unwrap();
+/// assert_eq!(spec.fill, '*');
+/// assert_eq!(spec.align, Some(Align::Center));
+/// assert_eq!(spec.width, Some(Count::Fixed(10)));
+/// assert_eq!(spec.precision, Some(Count::Fixed(2)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatSpec {
+    /// Character used for padding, a space by default
+    pub fill: char,
+    /// Alignment, or `None` for the value's default
+    pub align: Option<Align>,
+    /// Whether non-negative numbers get a `+`
+    pub sign: bool,
+    /// Whether numbers are padded with zeros after the sign
+    pub zero: bool,
+    /// Minimum width in characters
+    pub width: Option<Count>,
+    /// Whether the digits of numbers are grouped by thousands
+    pub grouping: bool,
+    /// Decimals for numbers, maximum length for strings
+    pub precision: Option<Count>,
+    /// How numbers are presented
+    pub style: NumberStyle,
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            sign: false,
+            zero: false,
+            width: None,
+            grouping: false,
+            precision: None,
+            style: NumberStyle::Plain,
+        }
+    }
+}
+
+fn align(ch: char) -> Option<Align> {
+    match ch {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+/// Reads a count starting at `*pos`.
+fn count(chars: &[char], pos: &mut usize) -> Option<Count> {
+    let start = *pos;
+
+    match chars.get(start) {
+        Some(c) if c.is_ascii_digit() => {
+            while chars.get(*pos).is_some_and(char::is_ascii_digit) {
+                *pos += 1;
+            }
+
+            chars[start..*pos]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .ok()
+                .map(Count::Fixed)
+        }
+        Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+            while chars
+                .get(*pos)
+                .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                *pos += 1;
+            }
+
+            Some(Count::Variable(Cow::Owned(
+                chars[start..*pos].iter().collect(),
+            )))
+        }
+        _ => None,
+    }
+}
+
+impl std::str::FromStr for FormatSpec {
+    type Err = SyntaxError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut out = Self::default();
+        let mut pos = 0;
+
+        if let Some(a) = chars.get(1).copied().and_then(align) {
+            out.fill = chars[0];
+            out.align = Some(a);
+            pos = 2;
+        } else if let Some(a) = chars.first().copied().and_then(align) {
+            out.align = Some(a);
+            pos = 1;
+        }
+
+        if chars.get(pos) == Some(&'+') {
+            out.sign = true;
+            pos += 1;
+        }
+
+        if chars.get(pos) == Some(&'0') {
+            out.zero = true;
+            pos += 1;
+        }
+
+        out.width = count(&chars, &mut pos);
+
+        if chars.get(pos) == Some(&',') {
+            out.grouping = true;
+            pos += 1;
+        }
+
+        if chars.get(pos) == Some(&'.') {
+            pos += 1;
+            out.precision = count(&chars, &mut pos);
+
+            if out.precision.is_none() {
+                return Err(SyntaxError::new(format!(
+                    "expected a precision after '.' in format spec '{}'",
+                    spec
+                )));
+            }
+        }
+
+        let style = match chars.get(pos) {
+            Some('$') => Some(NumberStyle::Currency),
+            Some('%') => Some(NumberStyle::Percent),
+            _ => None,
+        };
+
+        if let Some(style) = style {
+            out.style = style;
+            pos += 1;
+        }
+
+        if let Some(c) = chars.get(pos) {
+            return Err(SyntaxError::new(format!(
+                "unexpected '{}' in format spec '{}'",
+                c, spec
+            )));
+        }
+
+        Ok(out)
+    }
+}
+
+impl FormatSpec {
+    /// Formats a value according to this spec.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DirectiveError` if a width or precision variable is missing
+    /// or is not a non-negative integer.
+    pub fn apply(&self, value: &Value, scope: &Scope) -> Result<String, DirectiveError> {
+        let precision = self
+            .precision
+            .as_ref()
+            .map(|p| p.resolve(scope))
+            .transpose()?;
+        let width = self
+            .width
+            .as_ref()
+            .map(|w| w.resolve(scope))
+            .transpose()?
+            .unwrap_or(0);
+
+        // Padding and precision allocate, so they count towards the output limit
+        scope.check_output(width.max(precision.unwrap_or(0)))?;
+
+        let locale = scope.env().locale();
+
+        let (negative, digits) = match value {
+            Value::Int(i) => (*i < 0, self.int_digits(*i, precision)),
+            Value::Float(f) => (
+                f.is_sign_negative() && !f.is_nan(),
+                self.float_digits(*f, precision),
+            ),
+            other => {
+                let text = Cow::<'static, str>::from_value(other).unwrap_or_default();
+
+                let text: Cow<str> = match precision {
+                    Some(p) => Cow::Owned(text.chars().take(p).collect()),
+                    None => text,
+                };
+
+                return Ok(self.pad(&["", &text], width, false));
+            }
+        };
+
+        let body = locale.localize(&digits, self.grouping);
+        let sign = Self::sign_of(self.sign, negative);
+
+        let (prefix, suffix) = match self.style {
+            NumberStyle::Plain => ("", ""),
+            NumberStyle::Currency => (&*locale.currency_prefix, &*locale.currency_suffix),
+            NumberStyle::Percent => ("", &*locale.percent_suffix),
+        };
+
+        if self.zero {
+            let len: usize = [sign, prefix, &body, suffix]
+                .iter()
+                .map(|part| part.chars().count())
+                .sum();
+
+            let zeros = "0".repeat(width.saturating_sub(len));
+
+            return Ok([sign, prefix, &zeros, &body, suffix].concat());
+        }
+
+        Ok(self.pad(&[sign, prefix, &body, suffix], width, true))
+    }
+
+    /// Writes the unsigned digits of an integer, with `.` as decimal separator.
+    fn int_digits(&self, i: i64, precision: Option<usize>) -> String {
+        let mut digits = i.unsigned_abs().to_astring();
+
+        if self.style == NumberStyle::Percent && i != 0 {
+            digits.push_str("00");
+        }
+
+        // Integers get decimals from an explicit precision, or by default
+        // when shown as currency
+        let decimals = match self.style {
+            NumberStyle::Plain => precision.unwrap_or(0),
+            NumberStyle::Currency => precision.unwrap_or(2),
+            NumberStyle::Percent => precision.unwrap_or(0),
+        };
+
+        if decimals > 0 {
+            digits.push('.');
+            digits.extend(std::iter::repeat_n('0', decimals));
+        }
+
+        digits
+    }
+
+    /// Writes the unsigned digits of a float, with `.` as decimal separator.
+    fn float_digits(&self, f: f64, precision: Option<usize>) -> String {
+        let f = match self.style {
+            NumberStyle::Percent => f.abs() * 100.0,
+            _ => f.abs(),
+        };
+
+        let precision = match self.style {
+            NumberStyle::Currency => precision.or(Some(2)),
+            _ => precision,
+        };
+
+        match precision {
+            Some(p) => format!("{:.*}", p, f),
+            None => f.to_astring(),
+        }
+    }
+
+    /// Pads the concatenated `parts` to `width` with the fill character.
+    fn pad(&self, parts: &[&str], width: usize, numeric: bool) -> String {
+        let len: usize = parts.iter().map(|part| part.chars().count()).sum();
+        let padding = width.saturating_sub(len);
+
+        let align = self
+            .align
+            .unwrap_or(if numeric { Align::Right } else { Align::Left });
+
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+
+        let mut out = String::with_capacity(len + padding);
+        out.extend(std::iter::repeat_n(self.fill, before));
+        parts.iter().for_each(|part| out.push_str(part));
+        out.extend(std::iter::repeat_n(self.fill, after));
+
+        out
+    }
+
+    fn sign_of(plus: bool, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if plus {
+            "+"
+        } else {
+            ""
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Sequences of compiled directives.
+//!
+//! A [`Fragment`] is the body of a template, or of a construct nested in one
+//! such as a macro. Rendering a fragment runs its directives in order,
+//! escaping the output of parsed directives and applying the environment's
+//! [`MissingVariable`] policy. Variables assigned with `{set name = ...}` are
+//! bound in a frame of [`Locals`] that the following directives see. Errors
+//! of parsed directives are wrapped with their [`Location`].
+
+use std::{borrow::Cow, sync::Arc};
+
+use crate::{
+    Value,
+    arg::Resolvable,
+    directive::Directive,
+    env::{Locals, MissingVariable, Scope},
+    err::{DirectiveError, Location},
+    escape::Escape,
+    lexer::Span,
+};
+
+/// Compiled directives with the source text of the parsed ones.
+#[derive(Default, Clone)]
+pub(crate) struct Fragment {
+    pub(crate) directives: Vec<Arc<dyn Directive + Send + Sync>>,
+    /// Source text of the parsed directives, ordered by index.
+    pub(crate) sources: Vec<DirectiveSource>,
+}
+
+/// The template text a parsed directive was compiled from.
+#[derive(Clone)]
+pub(crate) struct DirectiveSource {
+    /// Index of the directive in `Fragment::directives`
+    pub(crate) index: usize,
+    /// The directive as written, delimiters included
+    pub(crate) text: Box<str>,
+    /// Where the directive's content is in the template it was compiled from
+    pub(crate) span: Span,
+    /// The variable the directive's value is assigned to, for `{set name = ...}`
+    pub(crate) binding: Option<Box<str>>,
+    /// The name of the template, when it was compiled by a loader
+    pub(crate) template: Option<Arc<str>>,
+    /// The whole construct as written, for directives spanning several tags
+    /// such as `{repeat n}...{endrepeat}`
+    pub(crate) original: Option<Box<str>>,
+}
+
+impl DirectiveSource {
+    /// Returns where the directive is, for error messages.
+    pub(crate) fn location(&self) -> Location {
+        Location {
+            template: self.template.as_deref().map(str::to_string),
+            span: self.span,
+            directive: self.text.to_string(),
+        }
+    }
+
+    /// Wraps the error of the directive with its location, unless a nested
+    /// directive did already.
+    fn locate(&self, err: DirectiveError) -> DirectiveError {
+        match err {
+            err @ DirectiveError::At { .. } => err,
+            err => DirectiveError::At {
+                location: self.location(),
+                source: Box::new(err),
+            },
+        }
+    }
+}
+
+impl Fragment {
+    /// Number of directives in the fragment.
+    pub(crate) fn len(&self) -> usize {
+        self.directives.len()
+    }
+
+    /// Appends the directives in `range` of `src`, with their sources.
+    pub(crate) fn copy(&mut self, src: &Self, range: std::ops::Range<usize>) {
+        let first = src
+            .sources
+            .partition_point(|source| source.index < range.start);
+
+        for source in src.sources[first..]
+            .iter()
+            .take_while(|source| source.index < range.end)
+        {
+            self.sources.push(DirectiveSource {
+                index: self.len() + source.index - range.start,
+                text: source.text.clone(),
+                span: source.span,
+                binding: source.binding.clone(),
+                template: source.template.clone(),
+                original: source.original.clone(),
+            });
+        }
+
+        self.directives
+            .extend(src.directives[range].iter().cloned());
+    }
+
+    /// Renders the directives in order.
+    ///
+    /// Parsed directives are escaped with the scope's [`Escape`] mode unless
+    /// they produce a [`Value::Safe`]. Missing variables are handled by the
+    /// environment's [`MissingVariable`] policy. When the scope collects errors,
+    /// failing directives are recorded and render as the environment's error
+    /// marker, otherwise the first error is returned with the location of the
+    /// directive that raised it. Assignments render nothing
+    /// and bind their value for the rest of the fragment. Each directive uses
+    /// one unit of fuel, and the output is checked against the output limit;
+    /// exceeding either is also reported at the directive's location.
+    pub(crate) fn render(&self, scope: &Scope) -> Result<String, DirectiveError> {
+        let env = scope.env();
+        let escape = scope.escape();
+        let mut output = String::with_capacity(self.directives.len() * 8);
+        let mut sources = self.sources.iter().peekable();
+        let vars = scope.variables();
+        let mut locals = Locals::default();
+
+        for (index, directive) in self.directives.iter().enumerate() {
+            // Only parsed directives are escaped, never the template text
+            let source = sources.next_if(|source| source.index == index);
+            let parsed = source.is_some();
+            let binding = source.and_then(|source| source.binding.as_deref());
+            let layered = vars.with_locals(&locals);
+            let scope = &scope.with_variables(layered);
+            let mut bound = None;
+            let locate = |err| match source {
+                Some(source) => source.locate(err),
+                None => err,
+            };
+
+            scope.use_fuel().map_err(locate)?;
+
+            let result = if binding.is_some() {
+                directive.value(scope).map(|value| {
+                    bound = Some(value);
+                    Cow::Borrowed("")
+                })
+            } else if parsed && escape != Escape::None {
+                directive.value(scope).map(|value| match value {
+                    Value::Safe(text) => text,
+                    value => {
+                        let text = Cow::<'static, str>::from_value(&value).unwrap_or_default();
+                        Cow::Owned(escape.escape(&text).into_owned())
+                    }
+                })
+            } else {
+                directive.exec(scope)
+            };
+
+            match result {
+                Ok(result) => output.push_str(&result),
+                Err(DirectiveError::NotFound { .. })
+                    if *env.missing_variable() != MissingVariable::Error =>
+                {
+                    output.push_str(self.missing_output(index, env.missing_variable()));
+                }
+                Err(err) => {
+                    let err = locate(err);
+
+                    match scope.collector() {
+                        Some(collector) => {
+                            collector.recover(err)?;
+                            output.push_str(env.error_marker());
+                        }
+                        None => return Err(err),
+                    }
+                }
+            }
+
+            scope.check_output(output.len()).map_err(locate)?;
+
+            if let (Some(name), Some(value)) = (binding, bound) {
+                locals.bind(Cow::Owned(name.to_string()), value);
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Returns what the directive at `index` renders as when a variable is missing.
+    ///
+    /// Assignments render nothing, as they do when the variable is present.
+    fn missing_output<'a>(&'a self, index: usize, policy: &'a MissingVariable) -> &'a str {
+        let source = self
+            .sources
+            .binary_search_by_key(&index, |source| source.index)
+            .ok()
+            .map(|i| &self.sources[i]);
+
+        if source.is_some_and(|source| source.binding.is_some()) {
+            return "";
+        }
+
+        match policy {
+            MissingVariable::Error | MissingVariable::Empty => "",
+            MissingVariable::Original => source.map_or("", |source| {
+                source.original.as_deref().unwrap_or(&source.text)
+            }),
+            MissingVariable::Placeholder(text) => text,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Rust functions callable from templates.
+//!
+//! Functions are registered on an [`Environment`](crate::Environment) and invoked
+//! from templates with call syntax, e.g. `{max(a, b)}`. Typed closures get their
+//! arity and argument types checked through [`Resolvable`] before they run.
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use crate::{Value, arg::Resolvable, err::DirectiveError};
+
+/// A function that can be called from a template.
+///
+/// Most functions are registered as plain closures through
+/// [`Environment::register`](crate::Environment::register), which implements this
+/// trait for them. Implement it directly for functions that need full control over
+/// their arguments.
+pub trait Function: Send + Sync {
+    /// Calls the function with already evaluated arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the function was called by, for error messages
+    /// * `args` - The evaluated call arguments
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DirectiveError` if the arguments are invalid or the function fails.
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, DirectiveError>;
+}
+
+/// Values that a registered function can return.
+///
+/// Implemented for every type convertible into a [`Value`], and for `Result`s of
+/// those types so that functions can fail with a `DirectiveError`.
+pub trait FunctionOutput {
+    /// Converts the returned value into the function's result.
+    fn into_result(self) -> Result<Value, DirectiveError>;
+}
+
+macro_rules! impl_function_output {
+    ($($ty:ty),*) => {
+        $(
+            impl FunctionOutput for $ty {
+                fn into_result(self) -> Result<Value, DirectiveError> {
+                    Ok(Value::from(self))
+                }
+            }
+        )*
+    };
+}
+
+impl_function_output!(
+    Value,
+    i64,
+    f64,
+    bool,
+    String,
+    &'static str,
+    Cow<'static, str>
+);
+
+impl<T: FunctionOutput> FunctionOutput for Result<T, DirectiveError> {
+    fn into_result(self) -> Result<Value, DirectiveError> {
+        self.and_then(FunctionOutput::into_result)
+    }
+}
+
+/// Closures that can be registered as template functions.
+///
+/// Implemented for `Fn` closures taking up to four arguments of any
+/// [`Resolvable`] type and returning a [`FunctionOutput`]. The `Args` parameter
+/// only exists to tell the implementations apart and is inferred.
+pub trait IntoFunction<Args>: Send + Sync + 'static {
+    /// Wraps the closure into a type-erased [`Function`].
+    fn into_function(self) -> Box<dyn Function>;
+}
+
+/// A closure with statically known argument types.
+struct Typed<F, Args> {
+    func: F,
+    _args: PhantomData<fn(Args)>,
+}
+
+/// Converts the argument at `position` for the function `name`.
+fn argument<T: Resolvable>(
+    name: &str,
+    args: &[Value],
+    position: usize,
+) -> Result<T, DirectiveError> {
+    T::from_value(&args[position]).ok_or_else(|| DirectiveError::FunctionArgument {
+        function: name.to_string(),
+        position: position + 1,
+        expected: T::TYPE_NAME,
+        found: args[position].type_name().to_string(),
+    })
+}
+
+macro_rules! impl_into_function {
+    ($count:expr $(, $arg:ident $idx:tt)*) => {
+        impl<F, R $(, $arg)*> Function for Typed<F, ($($arg,)*)>
+        where
+            F: Fn($($arg),*) -> R + Send + Sync,
+            R: FunctionOutput,
+            $($arg: Resolvable,)*
+        {
+            #[allow(unused_variables)]
+            fn call(&self, name: &str, args: &[Value]) -> Result<Value, DirectiveError> {
+                if args.len() != $count {
+                    return Err(DirectiveError::ArityMismatch {
+                        function: name.to_string(),
+                        expected: $count,
+                        found: args.len(),
+                    });
+                }
+
+                (self.func)($(argument::<$arg>(name, args, $idx)?),*).into_result()
+            }
+        }
+
+        impl<F, R $(, $arg)*> IntoFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: FunctionOutput + 'static,
+            $($arg: Resolvable + 'static,)*
+        {
+            fn into_function(self) -> Box<dyn Function> {
+                Box::new(Typed {
+                    func: self,
+                    _args: PhantomData,
+                })
+            }
+        }
+    };
+}
+
+impl_into_function!(0);
+impl_into_function!(1, A 0);
+impl_into_function!(2, A 0, B 1);
+impl_into_function!(3, A 0, B 1, C 2);
+impl_into_function!(4, A 0, B 1, C 2, D 3);
+
+/// A closure receiving the raw argument list, registered through
+/// [`Environment::register_variadic`](crate::Environment::register_variadic).
+pub(crate) struct Variadic<F>(pub(crate) F);
+
+impl<F> Function for Variadic<F>
+where
+    F: Fn(&[Value]) -> Result<Value, DirectiveError> + Send + Sync,
+{
+    fn call(&self, _name: &str, args: &[Value]) -> Result<Value, DirectiveError> {
+        (self.0)(args)
+    }
+}
@@ -1,4 +1,6 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt};
+
+use crate::err::LexError;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Token<'a> {
@@ -16,14 +18,16 @@ pub enum Token<'a> {
     /// An integer literal.
     ///
     /// Contains the string representation of the integer for parsing.
-    /// Examples: `"42"`, `"0"`, `"1000"`
+    /// Decimal and `0x` hexadecimal forms are accepted, with `_` separators.
+    /// The sign is a separate `Minus` token.
+    /// Examples: `"42"`, `"0"`, `"1_000"`, `"0xff"`
     Int(&'a str),
 
     /// A floating-point literal.
     ///
     /// Contains the string representation of the float for parsing.
-    /// Must contain a decimal point followed by digits.
-    /// Examples: `"3.14"`, `"0.5"`
+    /// Must contain a decimal point followed by digits, an exponent, or both.
+    /// Examples: `"3.14"`, `"0.5"`, `"1e6"`, `"2.5E-3"`
     Float(&'a str),
 
     /// Left parenthesis `(`.
@@ -42,9 +46,13 @@ pub enum Token<'a> {
     Colon,
     /// Semicolon `;`.
     Semicolon,
+    /// Comma `,` (separates function call arguments).
+    Comma,
 
     /// Question mark `?` (used in ternary conditionals).
     Question,
+    /// Double question mark `??` (null coalescing).
+    Coalesce,
     /// Pipe `|` (single pipe, not logical OR).
     Pipe,
     /// Underscore `_`.
@@ -91,6 +99,13 @@ pub enum Token<'a> {
     /// Logical OR `||`.
     Or,
 
+    /// A string literal missing its closing quote.
+    ///
+    /// Contains everything from the opening quote to the end of the input.
+    /// Compiling a template containing this token fails with
+    /// `TemplateError::UnterminatedLiteral`.
+    UnterminatedLiteral(Cow<'a, str>),
+
     /// An unknown/unexpected character.
     ///
     /// Used when the lexer encounters a character it doesn't recognize.
@@ -98,10 +113,72 @@ pub enum Token<'a> {
     Unknown(char),
 }
 
+/// A byte range in the lexed input.
+///
+/// Spans produced by [`TemplateLexer::spanned`] are relative to the string
+/// given to the lexer, so for a directive they are relative to the text
+/// between the delimiters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    /// Byte offset of the first byte
+    pub start: usize,
+    /// Byte offset right after the last byte
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a span covering `start..end`.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns the same span moved `offset` bytes to the right.
+    pub fn offset(self, offset: usize) -> Self {
+        Self::new(self.start + offset, self.end + offset)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A token together with the bytes of the input it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<'a> {
+    /// The token
+    pub token: Token<'a>,
+    /// Where the token appears in the input, including quotes for literals
+    pub span: Span,
+}
+
+/// Tokenizes the content of a directive.
+///
+/// The lexer is an iterator over bare [`Token`]s. Use [`TemplateLexer::spanned`]
+/// to also get the position of each token and to have unknown characters and
+/// unterminated literals reported as [`LexError`]s.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Span, TemplateLexer, Token};
+///
+/// let tokens: Vec<_> = TemplateLexer::new("x > 5").collect();
+/// assert_eq!(tokens, [Token::Ident("x"), Token::GreaterThan, Token::Int("5")]);
+///
+/// let spans: Vec<_> = TemplateLexer::new("x > 5")
+///     .spanned()
+///     .map(|t| t.unwrap().span)
+///     .collect();
+/// assert_eq!(spans, [Span::new(0, 1), Span::new(2, 3), Span::new(4, 5)]);
+/// ```
 pub struct TemplateLexer<'a> {
     input: &'a str,
     bytes: &'a [u8],
     cursor: usize,
+    /// Set when the last literal read ran to the end of the input.
+    unterminated: bool,
 }
 
 impl<'a> TemplateLexer<'a> {
@@ -110,9 +187,34 @@ impl<'a> TemplateLexer<'a> {
             input,
             bytes: input.as_bytes(),
             cursor: 0,
+            unterminated: false,
         }
     }
 
+    /// Turns the lexer into an iterator over spanned tokens.
+    ///
+    /// Unknown characters and string literals missing their closing quote
+    /// are yielded as `Err(LexError)` with their location; lexing continues
+    /// after them.
+    pub fn spanned(self) -> SpannedTokens<'a> {
+        SpannedTokens(self)
+    }
+
+    /// Reads the next token along with its span.
+    pub fn next_spanned(&mut self) -> Option<Result<Spanned<'a>, LexError>> {
+        self.skip_whitespace();
+
+        let start = self.cursor;
+        let token = self.next_token()?;
+        let span = Span::new(start, self.cursor.min(self.bytes.len()));
+
+        Some(match token {
+            Token::Unknown(ch) => Err(LexError::UnknownCharacter { ch, span }),
+            Token::UnterminatedLiteral(_) => Err(LexError::UnterminatedLiteral { span }),
+            token => Ok(Spanned { token, span }),
+        })
+    }
+
     #[inline]
     fn current(&self) -> u8 {
         if self.cursor < self.bytes.len() {
@@ -162,6 +264,10 @@ impl<'a> TemplateLexer<'a> {
             }
         }
 
+        if tmp_cursor >= self.bytes.len() {
+            self.unterminated = true;
+        }
+
         if !escaped {
             let len = tmp_cursor - start;
             self.cursor = tmp_cursor + 1; // skip closing quote
@@ -204,6 +310,17 @@ impl<'a> TemplateLexer<'a> {
         Cow::Owned(out)
     }
 
+    fn literal(&mut self, del: char) -> Token<'a> {
+        self.unterminated = false;
+        let literal = self.read_literal(del);
+
+        if self.unterminated {
+            Token::UnterminatedLiteral(literal)
+        } else {
+            Token::Literal(literal)
+        }
+    }
+
     fn read_ident(&mut self, start: usize) -> &'a str {
         while self.cursor < self.bytes.len() {
             let b = self.bytes[self.cursor];
@@ -219,18 +336,36 @@ impl<'a> TemplateLexer<'a> {
     }
 
     fn read_number(&mut self, start: usize) -> Token<'a> {
+        if self.bytes[start] == b'0'
+            && matches!(self.current(), b'x' | b'X')
+            && self.peek().is_ascii_hexdigit()
+        {
+            self.cursor += 1; // skip 'x'
+            self.skip_digits(u8::is_ascii_hexdigit);
+
+            return Token::Int(&self.input[start..self.cursor]);
+        }
+
         let mut is_float = false;
 
-        while self.cursor < self.bytes.len() && self.current().is_ascii_digit() {
-            self.cursor += 1;
-        }
+        self.skip_digits(u8::is_ascii_digit);
 
         if self.current() == b'.' && self.peek().is_ascii_digit() {
             is_float = true;
             self.cursor += 1; // skip '.'
+            self.skip_digits(u8::is_ascii_digit);
+        }
 
-            while self.cursor < self.bytes.len() && self.current().is_ascii_digit() {
-                self.cursor += 1;
+        if matches!(self.current(), b'e' | b'E') {
+            let digits = match self.peek() {
+                b'+' | b'-' => self.cursor + 2,
+                _ => self.cursor + 1,
+            };
+
+            if self.bytes.get(digits).is_some_and(u8::is_ascii_digit) {
+                is_float = true;
+                self.cursor = digits;
+                self.skip_digits(u8::is_ascii_digit);
             }
         }
 
@@ -243,6 +378,16 @@ impl<'a> TemplateLexer<'a> {
         }
     }
 
+    /// Skips digits accepted by `is_digit` and `_` separators.
+    #[inline]
+    fn skip_digits(&mut self, is_digit: fn(&u8) -> bool) {
+        while self.cursor < self.bytes.len()
+            && (is_digit(&self.bytes[self.cursor]) || self.bytes[self.cursor] == b'_')
+        {
+            self.cursor += 1;
+        }
+    }
+
     #[inline]
     fn check_double(
         &mut self,
@@ -278,7 +423,8 @@ impl<'a> TemplateLexer<'a> {
             b'}' => Some(Token::RCurly),
             b':' => Some(Token::Colon),
             b';' => Some(Token::Semicolon),
-            b'?' => Some(Token::Question),
+            b',' => Some(Token::Comma),
+            b'?' => Some(self.check_double(b'?', Token::Coalesce, Token::Question)),
             b'+' => Some(Token::Plus),
             b'-' => Some(Token::Minus),
             b'*' => Some(Token::Star),
@@ -289,8 +435,8 @@ impl<'a> TemplateLexer<'a> {
             b'>' => Some(self.check_double(b'=', Token::GreaterThanEquals, Token::GreaterThan)),
             b'&' => Some(self.check_double(b'&', Token::And, Token::Unknown('&'))),
             b'|' => Some(self.check_double(b'|', Token::Or, Token::Pipe)),
-            b'"' => Some(Token::Literal(self.read_literal('"'))),
-            b'\'' => Some(Token::Literal(self.read_literal('\''))),
+            b'"' => Some(self.literal('"')),
+            b'\'' => Some(self.literal('\'')),
             b'_' => {
                 let next = self.current();
                 if next.is_ascii_alphabetic() || next.is_ascii_digit() || next == b'_' {
@@ -301,7 +447,12 @@ impl<'a> TemplateLexer<'a> {
             }
             b if b.is_ascii_alphabetic() => Some(Token::Ident(self.read_ident(start))),
             b if b.is_ascii_digit() => Some(self.read_number(start)),
-            b => Some(Token::Unknown(b as char)),
+            _ => {
+                // Consume the whole character so that spans stay on char boundaries
+                let ch = self.input[start..].chars().next().unwrap_or_default();
+                self.cursor = start + ch.len_utf8();
+                Some(Token::Unknown(ch))
+            }
         }
     }
 }
@@ -313,3 +464,14 @@ impl<'a> Iterator for TemplateLexer<'a> {
         self.next_token()
     }
 }
+
+/// Iterator over spanned tokens, created by [`TemplateLexer::spanned`].
+pub struct SpannedTokens<'a>(TemplateLexer<'a>);
+
+impl<'a> Iterator for SpannedTokens<'a> {
+    type Item = Result<Spanned<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_spanned()
+    }
+}
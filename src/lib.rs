@@ -11,8 +11,11 @@
 //! - **Repeating patterns**: `{pattern:count}` - Repeat a pattern N times
 //! - **Conditionals**: `{condition ? true_value : false_value}` - Ternary expressions
 //! - **Comparisons**: Support for `==`, `!=`, `>`, `<`, `>=`, `<=`
+//! - **Function calls**: `{max(a, b)}` - Call Rust closures registered on an `Environment`
 //! - **Custom Logic**: You can implement custom logic using the `Logic` and `Parser` traits
 //! - **Custom delimiters**: Use any characters as open/close delimiters
+//! - **Raw blocks**: `{raw}...{endraw}` - Emit the enclosed text verbatim
+//! - **Escaping**: HTML, XML, JSON, shell and CSV escaping of substituted values
 //! - **Zero-copy where possible**: Leverages `Cow` for efficiency
 //!
 //! ## Example
@@ -40,28 +43,77 @@
 #![allow(clippy::should_implement_trait)]
 
 mod arg;
+mod collect;
 mod directive;
+mod env;
 mod err;
+mod escape;
+mod format;
+mod fragment;
+mod func;
 mod lexer;
+mod limits;
+mod loader;
+mod locale;
+mod macros;
 mod parser;
 mod traits;
 
 use std::{
     borrow::Cow,
     cell::RefCell,
+    cmp::Ordering,
     collections::HashMap,
     fmt::{self},
+    sync::Arc,
 };
 
+use collect::Collector;
+use fragment::{DirectiveSource, Fragment};
+use macros::{Macro, Macros};
+
 pub use arg::*;
+pub use collect::*;
 pub use directive::*;
+pub use env::*;
 pub use err::*;
+pub use escape::*;
+pub use format::*;
+pub use func::*;
 pub use lexer::*;
+pub use limits::*;
+pub use loader::*;
+pub use locale::*;
 pub use parser::*;
 
+/// Keyword opening a raw block, e.g. `{raw}`.
+const RAW_OPEN: &str = "raw";
+/// Keyword closing a raw block, e.g. `{endraw}`.
+const RAW_CLOSE: &str = "endraw";
+/// Prefix of an include, e.g. `{> header}`.
+const INCLUDE: char = '>';
+/// Keyword naming the parent of a template, e.g. `{extends 'base'}`.
+const EXTENDS: &str = "extends";
+/// Keyword opening an overridable block, e.g. `{block body}`.
+const BLOCK_OPEN: &str = "block";
+/// Keyword closing a block, e.g. `{endblock}`.
+const BLOCK_CLOSE: &str = "endblock";
+/// Keyword opening a macro definition, e.g. `{macro badge(label)}`.
+const MACRO_OPEN: &str = "macro";
+/// Keyword closing a macro definition, e.g. `{endmacro}`.
+const MACRO_CLOSE: &str = "endmacro";
+/// Keyword bringing in the macros of another template, e.g. `{import forms}`.
+const IMPORT: &str = "import";
+/// Keyword opening a repeated fragment, e.g. `{repeat n as i sep ', '}`.
+const REPEAT_OPEN: &str = "repeat";
+/// Keyword closing a repeated fragment, e.g. `{endrepeat}`.
+const REPEAT_CLOSE: &str = "endrepeat";
+/// Keyword assigning a template-local variable, e.g. `{set total = price * qty}`.
+const SET: &str = "set";
+
 /// A runtime value that can be stored in the template context.
 ///
-/// Values can be strings, integers, floats, or booleans. The type system
+/// Values can be strings, integers, floats, booleans, or null. The type system
 /// automatically handles conversions where appropriate (e.g., converting
 /// integers to strings for display).
 ///
@@ -74,6 +126,8 @@ pub use parser::*;
 /// let i = Value::Int(42);
 /// let f = Value::Float(3.14);
 /// let b = Value::Bool(true);
+/// let n = Value::Null;
+/// let html = Value::safe("<b>bold</b>");
 /// ```
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -85,6 +139,13 @@ pub enum Value {
     Float(f64),
     /// A boolean value
     Bool(bool),
+    /// The absence of a value; renders as an empty string and is falsy
+    Null,
+    /// A string that is already escaped for the output, such as trusted HTML.
+    ///
+    /// Behaves like `Str`, except that the template's [`Escape`] mode is not
+    /// applied to it.
+    Safe(Cow<'static, str>),
 }
 
 impl Value {
@@ -98,16 +159,127 @@ impl Value {
         Self::Str(Cow::Owned(s))
     }
 
+    /// Create a string value that is never escaped
+    pub fn safe(s: impl Into<Cow<'static, str>>) -> Self {
+        Self::Safe(s.into())
+    }
+
     /// Returns a human-readable name for the value's type.
     ///
     /// Used primarily in error messages to indicate type mismatches.
     pub fn type_name(&self) -> &str {
         match self {
-            Self::Str(_) => "string",
+            Self::Str(_) | Self::Safe(_) => "string",
             Self::Int(_) => "integer",
             Self::Float(_) => "float",
             Self::Bool(_) => "boolean",
+            Self::Null => "null",
+        }
+    }
+
+    /// Compares two values, returning `None` if they have no defined ordering.
+    ///
+    /// - Integers and floats compare numerically with each other. Integers are
+    ///   compared exactly, without going through `f64`.
+    /// - Strings compare lexicographically, even if they look like numbers.
+    ///   Safe strings compare like any other string.
+    /// - Booleans compare with booleans, `false` being less than `true`.
+    /// - Null is only equal to null.
+    ///
+    /// Any other pairing, and any comparison involving `NaN`, returns `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::Value;
+    /// use std::cmp::Ordering;
+    ///
+    /// assert_eq!(Value::Int(10).compare(&Value::Float(10.0)), Some(Ordering::Equal));
+    /// assert_eq!(Value::static_str("007").compare(&Value::static_str("6")), Some(Ordering::Less));
+    /// assert_eq!(Value::Int(1).compare(&Value::static_str("1")), None);
+    /// ```
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => Some(a.cmp(b)),
+            (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
+            (Self::Int(a), Self::Float(b)) => Self::compare_int_float(*a, *b),
+            (Self::Float(a), Self::Int(b)) => {
+                Self::compare_int_float(*b, *a).map(Ordering::reverse)
+            }
+            (Self::Str(a) | Self::Safe(a), Self::Str(b) | Self::Safe(b)) => Some(a.cmp(b)),
+            (Self::Bool(a), Self::Bool(b)) => Some(a.cmp(b)),
+            (Self::Null, Self::Null) => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+
+    /// Compares an integer with a float without losing integer precision.
+    fn compare_int_float(int: i64, float: f64) -> Option<Ordering> {
+        // 2^63, the first float above every i64
+        const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+        if float.is_nan() {
+            return None;
+        }
+
+        if float >= LIMIT {
+            return Some(Ordering::Less);
+        }
+
+        if float < -LIMIT {
+            return Some(Ordering::Greater);
         }
+
+        // The truncated float fits an i64; the fraction breaks ties
+        let whole = float.trunc();
+
+        Some(int.cmp(&(whole as i64)).then_with(|| {
+            0.0_f64
+                .partial_cmp(&(float - whole))
+                .unwrap_or(Ordering::Equal)
+        }))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Self::Int(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Self::Float(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<&'static str> for Value {
+    fn from(v: &'static str) -> Self {
+        Self::Str(Cow::Borrowed(v))
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Self::Str(Cow::Owned(v))
+    }
+}
+
+impl From<Cow<'static, str>> for Value {
+    fn from(v: Cow<'static, str>) -> Self {
+        Self::Str(v)
+    }
+}
+
+impl<T: Into<Self>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Self::Null, Into::into)
     }
 }
 
@@ -156,7 +328,56 @@ pub type Context = HashMap<&'static str, Value>;
 /// assert_eq!(tmpl.format(&ctx).unwrap(), "Hello World!");
 /// ```
 pub struct Template<const O: char, const C: char> {
-    directives: Vec<Box<dyn Directive + Send + Sync>>,
+    body: Fragment,
+    /// Escaping applied to the parsed directives, overriding the environment's.
+    escape: Option<Escape>,
+    /// Names of the templates included with `{> name}`, in order of appearance.
+    includes: Vec<Box<str>>,
+    /// Blocks in the order they are opened, so parents come before children.
+    blocks: Vec<Block>,
+    /// The template named by `{extends name}` and where the tag is, until a
+    /// loader resolves it.
+    extends: Option<(Box<str>, Location)>,
+    /// Macros defined in the template or imported by a loader.
+    macros: Macros,
+    /// Names of the templates whose macros are imported with `{import name}`.
+    imports: Vec<Box<str>>,
+}
+
+/// A named range of directives that a child template can override.
+struct Block {
+    name: Box<str>,
+    /// Index of the first directive of the block
+    start: usize,
+    /// Index after the last directive of the block
+    end: usize,
+    /// Index of the enclosing block in `Template::blocks`
+    parent: Option<usize>,
+}
+
+/// A macro definition whose `{endmacro}` has not been reached yet.
+struct OpenMacro {
+    name: Box<str>,
+    params: Vec<Cow<'static, str>>,
+    /// Index of the first directive of the body
+    first: usize,
+    /// Index of the first source of the body
+    first_source: usize,
+}
+
+/// A repeated fragment whose `{endrepeat}` has not been reached yet.
+struct OpenRepeat {
+    count: Argument,
+    index: Cow<'static, str>,
+    separator: Option<Argument>,
+    /// The opening directive as written, delimiters included
+    text: Box<str>,
+    /// Where the opening directive's content is in the template
+    span: Span,
+    /// Index of the first directive of the body
+    first: usize,
+    /// Index of the first source of the body
+    first_source: usize,
 }
 
 impl<const C: char, const O: char> fmt::Debug for Template<O, C> {
@@ -185,6 +406,8 @@ impl<const O: char, const C: char> Template<O, C> {
     /// Returns a `TemplateError` if:
     /// - A delimiter is not properly closed
     /// - A directive cannot be parsed
+    /// - A string literal is not closed
+    /// - A raw block is not closed
     ///
     /// # Examples
     ///
@@ -210,19 +433,25 @@ impl<const O: char, const C: char> Template<O, C> {
     /// let mut ctx = HashMap::new();
     /// ctx.insert("x", Value::Int(10));
     /// assert_eq!(tmpl.format(&ctx).unwrap(), "big");
+    ///
+    /// // Raw blocks
+    /// let tmpl = Template::<'{', '}'>::compile("{raw}fn main() { {{x}} }{endraw}").unwrap();
+    /// assert_eq!(tmpl.format(&HashMap::new()).unwrap(), "fn main() { {{x}} }");
     /// ```
     pub fn compile(input: impl AsRef<str>) -> Result<Self, TemplateError> {
         Self::compile_with_parser::<DefaultParser>(input.as_ref())
     }
 
-    /// Compiles a template string using a custom parser.
+    /// Compiles a template string using a custom parser type.
     ///
     /// This method allows you to use a custom parser implementation for specialized
-    /// template syntax or custom directives. The parser must implement the `Parser` trait.
+    /// template syntax or custom directives. The parser must implement the `Parser` trait
+    /// and `Default`; a default-constructed instance is used for compilation.
+    /// Use [`Template::compile_with`] to compile with a configured parser instance.
     ///
     /// # Type Parameters
     ///
-    /// * `P` - A type implementing the `Parser` trait
+    /// * `P` - A type implementing the `Parser` and `Default` traits
     ///
     /// # Arguments
     ///
@@ -258,19 +487,94 @@ impl<const O: char, const C: char> Template<O, C> {
     /// For custom parser implementations, implement the `Parser` trait:
     ///
     /// ```rust
-    /// use figura::{Parser, Token, Directive};
+    /// use figura::{Parser, Token, Directive, SyntaxError};
     ///
+    /// #[derive(Default)]
     /// struct MyCustomParser;
     ///
     /// impl Parser for MyCustomParser {
-    ///     fn parse(tokens: &[Token]) -> Option<Box<dyn Directive>> {
+    ///     fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
     ///         // Your custom parsing logic here
-    ///         None
+    ///         Err(SyntaxError::new("not implemented yet"))
     ///     }
     /// }
     /// ```
-    pub fn compile_with_parser<P: Parser>(input: &str) -> Result<Self, TemplateError> {
+    pub fn compile_with_parser<P: Parser + Default>(input: &str) -> Result<Self, TemplateError> {
+        Self::compile_with(&P::default(), input)
+    }
+
+    /// Compiles a template string using the given parser instance.
+    ///
+    /// Unlike [`Template::compile_with_parser`], the parser is passed by reference,
+    /// so it can carry configuration such as registered functions or strictness
+    /// flags, and can be reused across many compilations.
+    ///
+    /// # Arguments
+    ///
+    /// * `parser` - The parser used to turn each directive into a `Directive`
+    /// * `input` - The template string to compile
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Template)` - A compiled template ready for rendering
+    /// * `Err(TemplateError)` - If the template syntax is invalid
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TemplateError` if:
+    /// - A delimiter is not properly closed
+    /// - The parser cannot parse a directive
+    /// - A string literal is not closed
+    /// - A raw block is not closed
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Template, Parser, Token, Directive, ReplaceDirective, Argument, SyntaxError};
+    /// use std::borrow::Cow;
+    ///
+    /// struct PrefixParser {
+    ///     prefix: &'static str,
+    /// }
+    ///
+    /// impl Parser for PrefixParser {
+    ///     fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+    ///         match tokens {
+    ///             [Token::Ident(name)] => Ok(Box::new(ReplaceDirective(Argument::variable(
+    ///                 Cow::Owned(format!("{}{}", self.prefix, name)),
+    ///             )))),
+    ///             _ => Err(SyntaxError::new("expected a single variable name")),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let parser = PrefixParser { prefix: "user_" };
+    /// let tmpl = Template::<'{', '}'>::compile_with(&parser, "Hi {name}!").unwrap();
+    /// ```
+    pub fn compile_with<P: Parser + ?Sized>(
+        parser: &P,
+        input: impl AsRef<str>,
+    ) -> Result<Self, TemplateError> {
+        Self::compile_named(parser, input.as_ref(), None)
+    }
+
+    /// Compiles a template, naming it in the location of render errors.
+    pub(crate) fn compile_named<P: Parser + ?Sized>(
+        parser: &P,
+        input: &str,
+        name: Option<&str>,
+    ) -> Result<Self, TemplateError> {
+        let template_name: Option<Arc<str>> = name.map(Arc::from);
         let mut directives: Vec<Box<dyn Directive + Send + Sync>> = Vec::new();
+        let mut sources = Vec::new();
+        let mut includes = Vec::new();
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut open_blocks: Vec<usize> = Vec::new();
+        let mut extends = None;
+        let mut macros = Macros::new();
+        let mut imports = Vec::new();
+        let mut open_macro: Option<OpenMacro> = None;
+        let mut open_repeats: Vec<OpenRepeat> = Vec::new();
         let mut cursor = 0;
         let mut chars = input.char_indices().peekable();
 
@@ -330,14 +634,322 @@ impl<const O: char, const C: char> Template<O, C> {
 
                 let content = &input[start..end];
 
+                // Raw block: everything up to the matching end tag is emitted
+                // verbatim, without directive parsing or escape processing.
+                if content.trim() == RAW_OPEN {
+                    let (body_end, resume) =
+                        Self::find_raw_end(input, cursor).ok_or(TemplateError::UnclosedRawBlock)?;
+
+                    if body_end > cursor {
+                        directives.push(Box::new(LiteralDirective(Cow::Owned(
+                            input[cursor..body_end].to_string(),
+                        ))));
+                    }
+
+                    while let Some(&(i, _)) = chars.peek()
+                        && i < resume
+                    {
+                        chars.next();
+                    }
+
+                    cursor = resume;
+                    continue;
+                }
+
+                // Include: `{> name}` renders another template from the loader
+                if let Some(name) = content.trim_start().strip_prefix(INCLUDE) {
+                    let name = Self::include_name(name).ok_or_else(|| {
+                        TemplateError::DirectiveParsing {
+                            content: content.to_string(),
+                            error: SyntaxError::new("expected a template name after '>'"),
+                            span: Span::new(start, end),
+                        }
+                    })?;
+
+                    sources.push(DirectiveSource {
+                        index: directives.len(),
+                        text: format!("{}{}{}", O, content, C).into_boxed_str(),
+                        span: Span::new(start, end),
+                        binding: None,
+                        template: template_name.clone(),
+                        original: None,
+                    });
+                    includes.push(Box::from(name));
+                    directives.push(Box::new(IncludeDirective(Cow::Owned(name.to_string()))));
+                    continue;
+                }
+
+                // Inheritance: `{extends name}`, `{block name}` and `{endblock}`
+                // only shape the template and produce no directive
+                let invalid = |message: String| TemplateError::DirectiveParsing {
+                    content: content.to_string(),
+                    error: SyntaxError::new(message),
+                    span: Span::new(start, end),
+                };
+
+                if let Some(rest) = Self::named(content, EXTENDS) {
+                    let name = Self::include_name(rest).ok_or_else(|| {
+                        invalid("expected a template name after 'extends'".into())
+                    })?;
+
+                    if let Some((parent, _)) = &extends {
+                        return Err(invalid(format!("template already extends '{}'", parent)));
+                    }
+
+                    let location = Location {
+                        template: template_name.as_deref().map(str::to_string),
+                        span: Span::new(start, end),
+                        directive: format!("{}{}{}", O, content, C),
+                    };
+
+                    extends = Some((Box::from(name), location));
+                    continue;
+                }
+
+                if let Some(rest) = Self::named(content, IMPORT) {
+                    let name = Self::include_name(rest)
+                        .ok_or_else(|| invalid("expected a template name after 'import'".into()))?;
+
+                    imports.push(Box::from(name));
+                    continue;
+                }
+
+                if let Some(rest) = Self::named(content, MACRO_OPEN) {
+                    if !open_repeats.is_empty() {
+                        return Err(invalid("macros cannot be defined inside a repeat".into()));
+                    }
+
+                    if let Some(open) = &open_macro {
+                        return Err(invalid(format!(
+                            "macros cannot be defined inside macro '{}'",
+                            open.name
+                        )));
+                    }
+
+                    let (name, params) = Self::macro_signature(rest).ok_or_else(|| {
+                        invalid("expected a macro signature such as 'name(a, b)'".into())
+                    })?;
+
+                    if macros.contains_key(name.as_ref()) {
+                        return Err(invalid(format!("macro '{}' is already defined", name)));
+                    }
+
+                    open_macro = Some(OpenMacro {
+                        name,
+                        params,
+                        first: directives.len(),
+                        first_source: sources.len(),
+                    });
+                    continue;
+                }
+
+                if Self::keyword(content, MACRO_CLOSE).is_some_and(str::is_empty) {
+                    if !open_repeats.is_empty() {
+                        return Err(invalid("'endmacro' inside an open repeat".into()));
+                    }
+
+                    let OpenMacro {
+                        name,
+                        params,
+                        first,
+                        first_source,
+                    } = open_macro
+                        .take()
+                        .ok_or_else(|| invalid("'endmacro' without an open macro".into()))?;
+
+                    let body =
+                        Self::drain_fragment(&mut directives, &mut sources, first, first_source);
+
+                    macros.insert(name.clone(), Arc::new(Macro { name, params, body }));
+                    continue;
+                }
+
+                if Self::keyword(content, REPEAT_OPEN).is_some()
+                    && let Some(open) = Self::open_repeat(
+                        content,
+                        Span::new(start, end),
+                        directives.len(),
+                        sources.len(),
+                    )
+                    .map_err(|error| TemplateError::DirectiveParsing {
+                        content: content.to_string(),
+                        span: Self::error_span(content, &error).offset(start),
+                        error,
+                    })?
+                {
+                    open_repeats.push(open);
+                    continue;
+                }
+
+                if Self::keyword(content, REPEAT_CLOSE).is_some_and(str::is_empty) {
+                    let OpenRepeat {
+                        count,
+                        index,
+                        separator,
+                        text,
+                        span,
+                        first,
+                        first_source,
+                    } = open_repeats
+                        .pop()
+                        .ok_or_else(|| invalid("'endrepeat' without an open repeat".into()))?;
+
+                    let body =
+                        Self::drain_fragment(&mut directives, &mut sources, first, first_source);
+
+                    // The whole block, from the opening delimiter of the header
+                    // to the end of `{endrepeat}`
+                    let original = &input[span.start - O.len_utf8()..cursor];
+
+                    sources.push(DirectiveSource {
+                        index: directives.len(),
+                        text,
+                        span,
+                        binding: None,
+                        template: template_name.clone(),
+                        original: Some(Box::from(original)),
+                    });
+                    directives.push(Box::new(RepeatBodyDirective {
+                        count,
+                        index,
+                        separator,
+                        body,
+                    }));
+                    continue;
+                }
+
+                if let Some(rest) = Self::named(content, BLOCK_OPEN) {
+                    let name = Self::include_name(rest)
+                        .ok_or_else(|| invalid("expected a block name after 'block'".into()))?;
+
+                    if !open_repeats.is_empty() {
+                        return Err(invalid("blocks cannot be defined inside a repeat".into()));
+                    }
+
+                    if let Some(open) = &open_macro {
+                        return Err(invalid(format!(
+                            "blocks cannot be defined inside macro '{}'",
+                            open.name
+                        )));
+                    }
+
+                    if blocks.iter().any(|block| &*block.name == name) {
+                        return Err(invalid(format!("block '{}' is already defined", name)));
+                    }
+
+                    open_blocks.push(blocks.len());
+                    blocks.push(Block {
+                        name: Box::from(name),
+                        start: directives.len(),
+                        end: directives.len(),
+                        parent: open_blocks.iter().rev().nth(1).copied(),
+                    });
+                    continue;
+                }
+
+                if let Some(rest) = Self::named(content, BLOCK_CLOSE) {
+                    if !open_repeats.is_empty() {
+                        return Err(invalid("'endblock' inside an open repeat".into()));
+                    }
+
+                    if open_macro.is_some() {
+                        return Err(invalid("'endblock' inside an open macro".into()));
+                    }
+
+                    let index = open_blocks
+                        .pop()
+                        .ok_or_else(|| invalid("'endblock' without an open block".into()))?;
+                    let block = &mut blocks[index];
+
+                    if !rest.trim().is_empty() && Self::include_name(rest) != Some(&block.name) {
+                        return Err(invalid(format!(
+                            "'{}' closes block '{}'",
+                            content.trim(),
+                            block.name
+                        )));
+                    }
+
+                    block.end = directives.len();
+                    continue;
+                }
+
                 arena.borrow_mut().clear();
 
-                let tokens: Vec<Token> = TemplateLexer::new(content).collect();
+                let mut tokens: Vec<Token> = TemplateLexer::new(content).collect();
 
-                match P::parse(&tokens) {
-                    Some(directive) => directives.push(directive),
-                    None => return Err(TemplateError::DirectiveParsing(content.to_string())),
+                if let Some(index) = tokens
+                    .iter()
+                    .position(|token| matches!(token, Token::UnterminatedLiteral(_)))
+                {
+                    return Err(TemplateError::UnterminatedLiteral {
+                        content: content.to_string(),
+                        span: Self::token_span(content, index).offset(start),
+                    });
+                }
+
+                // Assignment: `{set name = expr}` binds the value of `expr` for
+                // the directives that follow
+                let (binding, first) = match tokens.as_slice() {
+                    [Token::Ident(SET), Token::Ident(name), Token::Assign, ..] => (Some(*name), 3),
+                    _ => (None, 0),
+                };
+
+                if let Some(name) = binding
+                    && tokens.len() == first
+                {
+                    return Err(invalid(format!("expected a value after '{} ='", name)));
                 }
+
+                // `expr | spec` formats the directive's value, if the parser
+                // does not give `|` a meaning of its own
+                let (expr, spec) = match Self::split_format_spec(content) {
+                    (expr, Some(spec)) if parser.splits_format_spec(&tokens[first..]) => {
+                        tokens = TemplateLexer::new(expr).collect();
+                        (expr, Some(spec))
+                    }
+                    _ => (content, None),
+                };
+
+                let mut directive = match parser.parse(&tokens[first..]) {
+                    Ok(directive) => directive,
+                    Err(mut error) => {
+                        error.token = error.token.map(|token| token + first);
+                        let span = Self::error_span(expr, &error).offset(start);
+
+                        return Err(TemplateError::DirectiveParsing {
+                            content: content.to_string(),
+                            error,
+                            span,
+                        });
+                    }
+                };
+
+                if let Some(spec_start) = spec {
+                    let rest = content[spec_start..].trim_start();
+                    let spec_text = rest.trim_end();
+
+                    let spec = spec_text.parse::<FormatSpec>().map_err(|error| {
+                        let offset = start + content.len() - rest.len();
+
+                        TemplateError::DirectiveParsing {
+                            content: content.to_string(),
+                            error,
+                            span: Span::new(offset, offset + spec_text.len()),
+                        }
+                    })?;
+
+                    directive = Box::new(FormatDirective(directive, spec));
+                }
+
+                sources.push(DirectiveSource {
+                    index: directives.len(),
+                    text: format!("{}{}{}", O, content, C).into_boxed_str(),
+                    span: Span::new(start, end),
+                    binding: binding.map(Box::from),
+                    template: template_name.clone(),
+                    original: None,
+                });
+                directives.push(directive);
             } else if ch == C
                 && let Some(&(_, next_char)) = chars.peek()
                 && next_char == C
@@ -361,7 +973,362 @@ impl<const O: char, const C: char> Template<O, C> {
             ))));
         }
 
-        Ok(Self { directives })
+        if !open_repeats.is_empty() {
+            return Err(TemplateError::UnclosedRepeat);
+        }
+
+        if let Some(open) = open_macro {
+            return Err(TemplateError::UnclosedMacro {
+                name: open.name.to_string(),
+            });
+        }
+
+        if let Some(&index) = open_blocks.last() {
+            return Err(TemplateError::UnclosedBlock {
+                name: blocks[index].name.to_string(),
+            });
+        }
+
+        Ok(Self {
+            body: Fragment {
+                directives: directives.into_iter().map(Arc::from).collect(),
+                sources,
+            },
+            escape: None,
+            includes,
+            blocks,
+            extends,
+            macros,
+            imports,
+        })
+    }
+
+    /// Builds the template a child renders as: the parent with the child's
+    /// blocks in place of its own.
+    ///
+    /// Blocks the parent does not define are ignored, as is everything in the
+    /// child outside of blocks. The result keeps the blocks, so it can be
+    /// extended in turn.
+    pub(crate) fn extend(parent: &Self, child: &Self) -> Self {
+        let mut macros = parent.macros.clone();
+        macros.extend(
+            child
+                .macros
+                .iter()
+                .map(|(name, m)| (name.clone(), m.clone())),
+        );
+
+        let mut out = Self {
+            body: Fragment::default(),
+            escape: child.escape.or(parent.escape),
+            includes: parent
+                .includes
+                .iter()
+                .chain(&child.includes)
+                .cloned()
+                .collect(),
+            blocks: Vec::new(),
+            extends: None,
+            macros,
+            imports: parent
+                .imports
+                .iter()
+                .chain(&child.imports)
+                .cloned()
+                .collect(),
+        };
+
+        out.splice(parent, None, Some(child), None);
+        out
+    }
+
+    /// Appends the directives of `block` in `src` (the whole template for
+    /// `None`), replacing nested blocks that `overrides` defines.
+    fn splice(
+        &mut self,
+        src: &Self,
+        block: Option<usize>,
+        overrides: Option<&Self>,
+        parent: Option<usize>,
+    ) {
+        let (mut cursor, end) = block.map_or((0, src.body.len()), |index| {
+            (src.blocks[index].start, src.blocks[index].end)
+        });
+
+        for (index, nested) in src.blocks.iter().enumerate() {
+            if nested.parent != block {
+                continue;
+            }
+
+            self.body.copy(&src.body, cursor..nested.start);
+
+            let out_index = self.blocks.len();
+            self.blocks.push(Block {
+                name: nested.name.clone(),
+                start: self.body.len(),
+                end: self.body.len(),
+                parent,
+            });
+
+            match overrides.and_then(|child| Some((child, child.block(&nested.name)?))) {
+                Some((child, replacement)) => {
+                    self.splice(child, Some(replacement), None, Some(out_index))
+                }
+                None => self.splice(src, Some(index), overrides, Some(out_index)),
+            }
+
+            self.blocks[out_index].end = self.body.len();
+            cursor = nested.end;
+        }
+
+        self.body.copy(&src.body, cursor..end);
+    }
+
+    /// Adds the macros of `other` that this template does not define itself.
+    pub(crate) fn import_macros(&mut self, other: &Self) {
+        for (name, m) in &other.macros {
+            self.macros.entry(name.clone()).or_insert_with(|| m.clone());
+        }
+    }
+
+    fn block(&self, name: &str) -> Option<usize> {
+        self.blocks.iter().position(|block| &*block.name == name)
+    }
+
+    /// Returns the rest of a directive starting with `keyword` as a whole word.
+    ///
+    /// Only closing keywords may stand alone, so `{block}`, `{macro}` or
+    /// `{repeat}` stay variables. Callers check that the rest is what the
+    /// keyword expects, and leave the directive to the parser otherwise.
+    fn keyword<'c>(content: &'c str, keyword: &str) -> Option<&'c str> {
+        let rest = content.trim().strip_prefix(keyword)?;
+
+        if rest.is_empty() {
+            return [BLOCK_CLOSE, MACRO_CLOSE, REPEAT_CLOSE]
+                .contains(&keyword)
+                .then_some(rest);
+        }
+
+        rest.starts_with(char::is_whitespace).then_some(rest)
+    }
+
+    /// Returns the rest of a directive made of `keyword` and a name, such as
+    /// `block body` or `extends 'base'`.
+    ///
+    /// A keyword followed by anything else is left to the parser, so
+    /// `{block ? 'y' : 'n'}` uses `block` as a variable.
+    fn named<'c>(content: &'c str, keyword: &str) -> Option<&'c str> {
+        let rest = Self::keyword(content, keyword)?;
+
+        match TemplateLexer::new(rest).next() {
+            None | Some(Token::Ident(_) | Token::Literal(_)) => Some(rest),
+            _ => None,
+        }
+    }
+
+    /// Locates a parser error inside the directive content.
+    ///
+    /// Errors without a token cover the whole content.
+    fn error_span(content: &str, error: &SyntaxError) -> Span {
+        match error.token {
+            Some(index) => Self::token_span(content, index),
+            None => Span::new(0, content.len()),
+        }
+    }
+
+    /// Locates the token at `index` inside the directive content.
+    ///
+    /// The content is lexed again with spans; an index past the last token points
+    /// at the end of the content.
+    fn token_span(content: &str, index: usize) -> Span {
+        TemplateLexer::new(content)
+            .spanned()
+            .nth(index)
+            .map(|token| token.map_or_else(|err| err.span(), |token| token.span))
+            .unwrap_or(Span::new(content.len(), content.len()))
+    }
+
+    /// Splits a directive into its expression and the start of its format spec.
+    ///
+    /// The spec follows the first `|` outside string literals; `||` is left to
+    /// the expression.
+    fn split_format_spec(content: &str) -> (&str, Option<usize>) {
+        let bytes = content.as_bytes();
+        let mut quote = None;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match (quote, bytes[i]) {
+                (Some(_), b'\\') => i += 1,
+                (Some(q), b) if b == q => quote = None,
+                (Some(_), _) => {}
+                (None, b @ (b'\'' | b'"')) => quote = Some(b),
+                (None, b'|') if bytes.get(i + 1) == Some(&b'|') => i += 1,
+                (None, b'|') => return (&content[..i], Some(i + 1)),
+                (None, _) => {}
+            }
+
+            i += 1;
+        }
+
+        (content, None)
+    }
+
+    /// Moves the directives from `first` on into a fragment of their own.
+    fn drain_fragment(
+        directives: &mut Vec<Box<dyn Directive + Send + Sync>>,
+        sources: &mut Vec<DirectiveSource>,
+        first: usize,
+        first_source: usize,
+    ) -> Fragment {
+        Fragment {
+            directives: directives.drain(first..).map(Arc::from).collect(),
+            sources: sources
+                .drain(first_source..)
+                .map(|source| DirectiveSource {
+                    index: source.index - first,
+                    ..source
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads the header of a repeat: `repeat count [as name] [sep separator]`.
+    ///
+    /// `span` locates the content in the template, and the body starts at
+    /// directive `first` and source `first_source`. Token
+    /// indices in errors refer to the whole directive content.
+    ///
+    /// Returns `None` if `repeat` is not followed by a count, so that
+    /// `{repeat > 0 ? 'a' : 'b'}` is left to the parser.
+    fn open_repeat(
+        content: &str,
+        span: Span,
+        first: usize,
+        first_source: usize,
+    ) -> Result<Option<OpenRepeat>, SyntaxError> {
+        let tokens: Vec<Token> = TemplateLexer::new(content).collect();
+        let mut pos = 1;
+
+        let Ok(Some(count)) = parse_operand(&tokens, &mut pos) else {
+            return Ok(None);
+        };
+
+        let index = match tokens.get(pos) {
+            Some(Token::Ident("as")) => match tokens.get(pos + 1) {
+                Some(Token::Ident(name)) => {
+                    pos += 2;
+                    Cow::Owned(name.to_string())
+                }
+                _ => {
+                    return Err(SyntaxError::at(
+                        pos + 1,
+                        "expected a variable name after 'as'",
+                    ));
+                }
+            },
+            _ => Cow::Borrowed("index"),
+        };
+
+        let separator = match tokens.get(pos) {
+            Some(Token::Ident("sep")) => {
+                pos += 1;
+
+                let separator = parse_operand(&tokens, &mut pos)?
+                    .ok_or_else(|| SyntaxError::at(pos, "expected a separator after 'sep'"))?;
+
+                Some(separator)
+            }
+            _ => None,
+        };
+
+        if pos != tokens.len() {
+            return Err(SyntaxError::at(
+                pos,
+                "expected 'as', 'sep' or the end of the repeat",
+            ));
+        }
+
+        Ok(Some(OpenRepeat {
+            count,
+            index,
+            separator,
+            text: format!("{}{}{}", O, content, C).into_boxed_str(),
+            span,
+            first,
+            first_source,
+        }))
+    }
+
+    /// Reads a macro signature such as `badge(label, color)`.
+    fn macro_signature(text: &str) -> Option<(Box<str>, Vec<Cow<'static, str>>)> {
+        let tokens: Vec<Token> = TemplateLexer::new(text).collect();
+
+        let [
+            Token::Ident(name),
+            Token::LParen,
+            params @ ..,
+            Token::RParen,
+        ] = tokens.as_slice()
+        else {
+            return None;
+        };
+
+        let mut names = Vec::new();
+
+        for (i, token) in params.iter().enumerate() {
+            match token {
+                Token::Ident(param) if i % 2 == 0 && !names.contains(&Cow::Borrowed(*param)) => {
+                    names.push(Cow::Owned(param.to_string()))
+                }
+                Token::Comma if i % 2 == 1 && i + 1 < params.len() => {}
+                _ => return None,
+            }
+        }
+
+        Some((Box::from(*name), names))
+    }
+
+    /// Reads the template name of an include, written bare or quoted.
+    fn include_name(text: &str) -> Option<&str> {
+        let text = text.trim();
+
+        let name = match text.chars().next()? {
+            quote @ ('\'' | '"') => text.strip_prefix(quote)?.strip_suffix(quote)?,
+            _ if text.contains(char::is_whitespace) => return None,
+            _ => text,
+        };
+
+        (!name.is_empty()).then_some(name)
+    }
+
+    /// Finds the end tag of a raw block whose body starts at `from`.
+    ///
+    /// Whitespace is allowed around the keyword, so `{endraw}` and `{ endraw }`
+    /// both close the block.
+    ///
+    /// # Returns
+    ///
+    /// The byte offset where the body ends and the offset right after the end tag,
+    /// or `None` if the block is never closed.
+    fn find_raw_end(input: &str, from: usize) -> Option<(usize, usize)> {
+        for (idx, _) in input[from..].match_indices(O) {
+            let body_end = from + idx;
+            let rest = input[body_end + O.len_utf8()..].trim_start();
+
+            let Some(rest) = rest.strip_prefix(RAW_CLOSE) else {
+                continue;
+            };
+
+            let rest = rest.trim_start();
+
+            if rest.starts_with(C) {
+                let resume = input.len() - rest.len() + C.len_utf8();
+                return Some((body_end, resume));
+            }
+        }
+
+        None
     }
 
     /// Renders the template using the provided context.
@@ -370,6 +1337,9 @@ impl<const O: char, const C: char> Template<O, C> {
     /// results into a final string. It pre-allocates a reasonable capacity to minimize
     /// allocations during rendering.
     ///
+    /// Templates rendered this way cannot call functions; use [`Template::format_with`]
+    /// to render with an [`Environment`].
+    ///
     /// # Arguments
     ///
     /// * `ctx` - A reference to the context containing variable values
@@ -385,6 +1355,7 @@ impl<const O: char, const C: char> Template<O, C> {
     /// - A referenced variable is not found in the context
     /// - A variable has an incompatible type for the operation
     /// - A literal value cannot be parsed as the required type
+    /// - A function is called (no functions are registered)
     ///
     /// # Examples
     ///
@@ -401,13 +1372,192 @@ impl<const O: char, const C: char> Template<O, C> {
     /// assert_eq!(output, "Hi Alice!");
     /// ```
     pub fn format(&self, ctx: &Context) -> Result<String, DirectiveError> {
-        let mut output = String::with_capacity(self.directives.len() * 8);
+        self.format_with(ctx, &Environment::default())
+    }
+
+    /// Renders the template using the provided context and environment.
+    ///
+    /// The environment supplies the functions templates can call.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - A reference to the context containing variable values
+    /// * `env` - The environment holding registered functions, the
+    ///   [`MissingVariable`] policy and the [`Escape`] mode
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DirectiveError` in the same cases as [`Template::format`], and if:
+    /// - A called function is not registered (`DirectiveError::UnknownFunction`)
+    /// - A function is called with the wrong number of arguments (`DirectiveError::ArityMismatch`)
+    /// - A function argument has an incompatible type (`DirectiveError::FunctionArgument`)
+    ///
+    /// Missing variables are only an error under [`MissingVariable::Error`], the
+    /// default; other policies render a replacement for the directive instead.
+    /// Errors of directives come wrapped in `DirectiveError::At`, which tells
+    /// where the failing directive is.
+    ///
+    /// Directive output is escaped with the template's [`Escape`] mode, or the
+    /// environment's if the template has none. Replacements for missing
+    /// variables are not escaped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Template, Context, Environment, Value};
+    ///
+    /// let mut env = Environment::new();
+    /// env.register("format_money", |amount: f64, currency: std::borrow::Cow<'static, str>| {
+    ///     format!("{:.2} {}", amount, currency)
+    /// });
+    ///
+    /// let tmpl = Template::<'{', '}'>::compile("Total: {format_money(total, 'EUR')}").unwrap();
+    ///
+    /// let mut ctx = Context::new();
+    /// ctx.insert("total", Value::Float(9.5));
+    ///
+    /// assert_eq!(tmpl.format_with(&ctx, &env).unwrap(), "Total: 9.50 EUR");
+    /// ```
+    pub fn format_with(&self, ctx: &Context, env: &Environment) -> Result<String, DirectiveError> {
+        self.format_in(&Scope::new(ctx, env))
+    }
+
+    /// Renders the template without stopping at directives that fail.
+    ///
+    /// Each failing directive renders as the environment's
+    /// [error marker](Environment::set_error_marker), and its error is
+    /// returned with the output, tagged with the directive's position.
+    /// Directives of macros and included templates are handled the same way.
+    /// Missing variables follow the [`MissingVariable`] policy as usual, and
+    /// are only collected under [`MissingVariable::Error`].
+    ///
+    /// Exceeding one of the environment's [`Limits`] still stops the render:
+    /// the error is collected and the output is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Context, Environment, Template, Value};
+    ///
+    /// let tmpl = Template::<'{', '}'>::compile("{a}, {b}, {c}").unwrap();
+    ///
+    /// let mut ctx = Context::new();
+    /// ctx.insert("b", Value::Int(2));
+    ///
+    /// let mut env = Environment::new();
+    /// env.set_error_marker("??");
+    ///
+    /// let rendered = tmpl.format_collect(&ctx, &env);
+    /// assert_eq!(rendered.output, "??, 2, ??");
+    /// assert_eq!(rendered.errors.len(), 2);
+    /// ```
+    pub fn format_collect(&self, ctx: &Context, env: &Environment) -> Rendered {
+        self.format_collect_in(&Scope::new(ctx, env))
+    }
+
+    /// Renders the template in an existing scope like
+    /// [`Template::format_collect`].
+    pub fn format_collect_in(&self, scope: &Scope) -> Rendered {
+        let collector = Collector::default();
+        let result = self.format_in(&scope.with_collector(&collector));
+
+        collector.finish(result)
+    }
+
+    /// Renders the template with a [`LayeredContext`], looking variables up
+    /// from its innermost layer outwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DirectiveError` in the same cases as [`Template::format_with`].
+    pub fn format_layered(
+        &self,
+        ctx: &LayeredContext,
+        env: &Environment,
+    ) -> Result<String, DirectiveError> {
+        self.format_in(&Scope::layered(ctx, env))
+    }
 
-        for directive in &self.directives {
-            let result = directive.exec(ctx)?;
-            output.push_str(&result);
+    /// Renders the template in an existing scope.
+    ///
+    /// Used to render included templates with the scope of the including one.
+    /// Behaves like [`Template::format_with`] with the scope's context and
+    /// environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DirectiveError` in the same cases as [`Template::format_with`].
+    pub fn format_in(&self, scope: &Scope) -> Result<String, DirectiveError> {
+        if let Some((parent, location)) = &self.extends {
+            return Err(DirectiveError::At {
+                location: location.clone(),
+                source: Box::new(DirectiveError::UnresolvedExtends {
+                    name: parent.to_string(),
+                }),
+            });
         }
 
-        Ok(output)
+        scope.fueled(|scope| {
+            let scope = scope
+                .with_escape(self.escape.unwrap_or(scope.escape()))
+                .with_macros(&self.macros);
+
+            self.body.render(&scope)
+        })
+    }
+
+    /// Sets how this template escapes the output of its directives.
+    ///
+    /// Overrides the mode set on the [`Environment`] used to render it. Text
+    /// written in the template and [`Value::Safe`] values are never escaped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{Context, Escape, Template, Value};
+    ///
+    /// let mut tmpl = Template::<'{', '}'>::compile("<p>{comment}</p>").unwrap();
+    /// tmpl.set_escape(Escape::Html);
+    ///
+    /// let mut ctx = Context::new();
+    /// ctx.insert("comment", Value::static_str("<script>"));
+    ///
+    /// assert_eq!(tmpl.format(&ctx).unwrap(), "<p>&lt;script&gt;</p>");
+    /// ```
+    pub fn set_escape(&mut self, escape: Escape) {
+        self.escape = Some(escape);
+    }
+
+    /// Returns the escaping mode set on this template, if any.
+    pub fn escape(&self) -> Option<Escape> {
+        self.escape
+    }
+
+    /// Returns the names of the templates included with `{> name}`.
+    pub fn includes(&self) -> impl Iterator<Item = &str> {
+        self.includes.iter().map(|name| &**name)
+    }
+
+    /// Returns the name of the template this one extends, if it has not been
+    /// resolved by a [`Loader`] yet.
+    pub fn extends(&self) -> Option<&str> {
+        self.extends.as_ref().map(|(name, _)| &**name)
+    }
+
+    /// Returns the names of the templates whose macros are imported with
+    /// `{import name}`.
+    pub fn imports(&self) -> impl Iterator<Item = &str> {
+        self.imports.iter().map(|name| &**name)
+    }
+
+    /// Returns the names of the macros this template can call.
+    pub fn macros(&self) -> impl Iterator<Item = &str> {
+        self.macros.keys().map(|name| &**name)
+    }
+
+    /// Returns the names of the blocks defined in this template, outer blocks
+    /// first.
+    pub fn blocks(&self) -> impl Iterator<Item = &str> {
+        self.blocks.iter().map(|block| &*block.name)
     }
 }
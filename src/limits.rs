@@ -0,0 +1,104 @@
+//! Bounds on the work a render may do.
+//!
+//! Templates written by untrusted users can ask for huge repetitions, deep
+//! macro recursion or endless computation. [`Limits`] set on the
+//! [`Environment`](crate::Environment) stop such renders with
+//! `DirectiveError::LimitExceeded` instead of exhausting memory or the stack.
+
+use std::fmt;
+
+/// Nesting depth allowed by default, deep enough for any reasonable template
+/// while keeping recursive macros from overflowing the stack.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Output size allowed by default, in bytes.
+pub const DEFAULT_MAX_OUTPUT: usize = 64 << 20;
+
+/// Count of a single repetition allowed by default.
+pub const DEFAULT_MAX_REPEAT: usize = 1_000_000;
+
+/// Render limits, `None` meaning unlimited.
+///
+/// The default bounds output size to [`DEFAULT_MAX_OUTPUT`], repetitions to
+/// [`DEFAULT_MAX_REPEAT`] and nesting depth to [`DEFAULT_MAX_DEPTH`], leaving
+/// fuel unlimited. Even without an output limit, output can never exceed
+/// `isize::MAX` bytes, the most a `String` can hold.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, DirectiveError, Environment, Limit, Limits, Template};
+///
+/// let mut env = Environment::new();
+/// env.set_limits(Limits {
+///     max_output: Some(1024),
+///     max_repeat: Some(100),
+///     ..Limits::default()
+/// });
+///
+/// let tmpl = Template::<'{', '}'>::compile("{'x':1000000}").unwrap();
+///
+/// assert!(matches!(
+///     tmpl.format_with(&Context::new(), &env).map_err(DirectiveError::into_root),
+///     Err(DirectiveError::LimitExceeded { limit: Limit::Repeat, max: 100 })
+/// ));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length of the output in bytes, also applied to the output of
+    /// macros, includes and repeated fragments
+    pub max_output: Option<usize>,
+    /// Maximum count of a single repetition
+    pub max_repeat: Option<usize>,
+    /// Maximum nesting of macro calls and includes
+    pub max_depth: Option<usize>,
+    /// Maximum number of directives executed and expressions evaluated in
+    /// one render, includes and macro calls included
+    pub fuel: Option<u64>,
+}
+
+impl Limits {
+    /// Limits that allow everything, including unbounded recursion and
+    /// outputs as large as memory allows.
+    pub const UNLIMITED: Self = Self {
+        max_output: None,
+        max_repeat: None,
+        max_depth: None,
+        fuel: None,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_output: Some(DEFAULT_MAX_OUTPUT),
+            max_repeat: Some(DEFAULT_MAX_REPEAT),
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            fuel: None,
+        }
+    }
+}
+
+/// Which of the [`Limits`] a render exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// [`Limits::max_output`]
+    Output,
+    /// [`Limits::max_repeat`]
+    Repeat,
+    /// [`Limits::max_depth`]
+    Depth,
+    /// [`Limits::fuel`]
+    Fuel,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Output => "output size",
+            Self::Repeat => "repeat count",
+            Self::Depth => "nesting depth",
+            Self::Fuel => "evaluation fuel",
+        })
+    }
+}
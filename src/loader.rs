@@ -0,0 +1,452 @@
+//! Named templates and includes.
+//!
+//! A [`Loader`] resolves template names through a [`TemplateSource`], such as
+//! a [`DirectorySource`] or a [`MemorySource`], and compiles each template
+//! once. Templates rendered through a loader can include other templates with
+//! `{> name}`; included templates see the same context as the including one.
+//! A template starting with `{extends name}` is merged with its parent when it
+//! is loaded, replacing the parent's `{block ...}` sections with its own, and
+//! `{import name}` makes the macros of another template callable.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, io,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use thiserror::Error;
+
+use crate::{
+    Context, Template,
+    collect::Rendered,
+    env::{Environment, LayeredContext, Scope},
+    err::{DirectiveError, TemplateError},
+    parser::{DefaultParser, Parser},
+};
+
+/// Where a [`Loader`] reads template sources from.
+///
+/// Implemented by [`DirectorySource`], [`MemorySource`] and closures taking a
+/// template name.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Loader, Template};
+///
+/// let loader = Loader::<'{', '}'>::new(|name: &str| {
+///     Ok(match name {
+///         "greeting" => Some("Hello {name}!".to_string()),
+///         _ => None,
+///     })
+/// });
+/// ```
+pub trait TemplateSource: Send + Sync {
+    /// Returns the source of the named template, or `None` if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the template exists but cannot be read.
+    fn load(&self, name: &str) -> io::Result<Option<String>>;
+}
+
+impl<F> TemplateSource for F
+where
+    F: Fn(&str) -> io::Result<Option<String>> + Send + Sync,
+{
+    fn load(&self, name: &str) -> io::Result<Option<String>> {
+        self(name)
+    }
+}
+
+/// Reads templates from files under a root directory.
+///
+/// Names are paths relative to the root, e.g. `partials/header`. Names that
+/// are absolute or contain `..` are rejected, so templates cannot escape the
+/// root.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{DirectorySource, Loader};
+///
+/// // `{> header}` reads `templates/header.html`
+/// let source = DirectorySource::new("templates").with_extension("html");
+/// let loader = Loader::<'{', '}'>::new(source);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+    extension: Option<String>,
+}
+
+impl DirectorySource {
+    /// Creates a source reading templates from `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            extension: None,
+        }
+    }
+
+    /// Appends an extension to every template name, e.g. `"html"`.
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        let relative = Path::new(name);
+
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        let mut path = self.root.join(relative);
+
+        if let Some(extension) = &self.extension {
+            path.as_mut_os_string().push(format!(".{}", extension));
+        }
+
+        Some(path)
+    }
+}
+
+impl TemplateSource for DirectorySource {
+    fn load(&self, name: &str) -> io::Result<Option<String>> {
+        let path = self.path(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("template name '{}' is not a relative path", name),
+            )
+        })?;
+
+        match std::fs::read_to_string(path) {
+            Ok(source) => Ok(Some(source)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Templates kept in memory, keyed by name.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, Loader, MemorySource, Value};
+///
+/// let mut source = MemorySource::new();
+/// source.insert("header", "== {title} ==");
+/// source.insert("page", "{> header}\n{body}");
+///
+/// let loader = Loader::<'{', '}'>::new(source);
+///
+/// let mut ctx = Context::new();
+/// ctx.insert("title", Value::static_str("News"));
+/// ctx.insert("body", Value::static_str("Nothing happened."));
+///
+/// let output = loader.render("page", &ctx, &Environment::new()).unwrap();
+/// assert_eq!(output, "== News ==\nNothing happened.");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    templates: HashMap<Cow<'static, str>, Cow<'static, str>>,
+}
+
+impl MemorySource {
+    /// Creates an empty source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a template, replacing any template with the same name.
+    pub fn insert(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        source: impl Into<Cow<'static, str>>,
+    ) {
+        self.templates.insert(name.into(), source.into());
+    }
+}
+
+impl TemplateSource for MemorySource {
+    fn load(&self, name: &str) -> io::Result<Option<String>> {
+        Ok(self.templates.get(name).map(|source| source.to_string()))
+    }
+}
+
+/// Renders the templates named by `{> name}` directives.
+///
+/// Implemented by [`Loader`]. A scope created with [`Scope::with_loader`]
+/// hands its resolver to the include directives it executes.
+pub trait IncludeResolver: Send + Sync {
+    /// Renders the named template in the given scope.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DirectiveError::Include` if the template cannot be loaded, or
+    /// any error raised while rendering it.
+    fn include(&self, name: &str, scope: &Scope) -> Result<String, DirectiveError>;
+}
+
+impl fmt::Debug for dyn IncludeResolver + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IncludeResolver")
+    }
+}
+
+/// Errors raised while loading or rendering a named template.
+#[derive(Debug, Error)]
+pub enum LoadError {
+    /// The source has no template with this name.
+    #[error("Template '{name}' was not found")]
+    NotFound { name: String },
+
+    /// The source failed to read the template.
+    #[error("Failed to read template '{name}': {source}")]
+    Io {
+        name: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The template could not be compiled.
+    #[error("Failed to compile template '{name}': {source}")]
+    Compile {
+        name: String,
+        #[source]
+        source: TemplateError,
+    },
+
+    /// Templates include or extend each other in a loop.
+    ///
+    /// `chain` lists the templates in include order, starting and ending with
+    /// the same name.
+    #[error("Templates depend on each other in a cycle: {}", chain.join(" -> "))]
+    Cycle { chain: Vec<String> },
+
+    /// The template failed to render.
+    #[error("Failed to render template '{name}': {source}")]
+    Render {
+        name: String,
+        #[source]
+        source: DirectiveError,
+    },
+}
+
+/// Compiles and caches named templates read from a [`TemplateSource`].
+///
+/// A template is compiled the first time it is requested, together with every
+/// template it includes, imports or extends, and reused afterwards. A template
+/// extending another is stored already merged with its parent. Cycles between
+/// templates are reported when the template is loaded, before anything is
+/// rendered.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, Loader, MemorySource, Value};
+///
+/// let mut source = MemorySource::new();
+/// source.insert("signature", "-- {author}");
+/// source.insert("mail", "Hi {to},\n\n{> signature}");
+///
+/// let loader = Loader::<'{', '}'>::new(source);
+///
+/// let mut ctx = Context::new();
+/// ctx.insert("to", Value::static_str("Bob"));
+/// ctx.insert("author", Value::static_str("Alice"));
+///
+/// let output = loader.render("mail", &ctx, &Environment::new()).unwrap();
+/// assert_eq!(output, "Hi Bob,\n\n-- Alice");
+/// ```
+pub struct Loader<const O: char, const C: char> {
+    source: Box<dyn TemplateSource>,
+    parser: Box<dyn Parser + Send + Sync>,
+    cache: RwLock<HashMap<String, Arc<Template<O, C>>>>,
+}
+
+impl<const O: char, const C: char> fmt::Debug for Loader<O, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cache = self.cache.read().unwrap_or_else(|err| err.into_inner());
+
+        f.debug_struct("Loader")
+            .field("templates", &cache.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<const O: char, const C: char> Loader<O, C> {
+    /// Creates a loader compiling templates with the [`DefaultParser`].
+    pub fn new(source: impl TemplateSource + 'static) -> Self {
+        Self::with_parser(source, DefaultParser)
+    }
+
+    /// Creates a loader compiling templates with the given parser.
+    pub fn with_parser(
+        source: impl TemplateSource + 'static,
+        parser: impl Parser + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            source: Box::new(source),
+            parser: Box::new(parser),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the compiled template with the given name.
+    ///
+    /// The template and the templates it includes are compiled on first use.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LoadError` if the template or one it includes is missing,
+    /// cannot be read or compiled, or if includes form a cycle.
+    pub fn get(&self, name: &str) -> Result<Arc<Template<O, C>>, LoadError> {
+        self.load(name, &mut Vec::new())
+    }
+
+    /// Renders the named template with the given context and environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LoadError` if the template cannot be loaded, or
+    /// `LoadError::Render` if rendering fails.
+    pub fn render(
+        &self,
+        name: &str,
+        ctx: &Context,
+        env: &Environment,
+    ) -> Result<String, LoadError> {
+        self.render_in(name, Scope::new(ctx, env))
+    }
+
+    /// Renders the named template with a [`LayeredContext`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LoadError` in the same cases as [`Loader::render`].
+    pub fn render_layered(
+        &self,
+        name: &str,
+        ctx: &LayeredContext,
+        env: &Environment,
+    ) -> Result<String, LoadError> {
+        self.render_in(name, Scope::layered(ctx, env))
+    }
+
+    /// Renders the named template without stopping at directives that fail,
+    /// like [`Template::format_collect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LoadError` if the template cannot be loaded. Errors raised
+    /// while rendering are part of the result.
+    pub fn render_collect(
+        &self,
+        name: &str,
+        ctx: &Context,
+        env: &Environment,
+    ) -> Result<Rendered, LoadError> {
+        let template = self.get(name)?;
+
+        Ok(template.format_collect_in(&Scope::new(ctx, env).with_loader(self)))
+    }
+
+    fn render_in(&self, name: &str, scope: Scope) -> Result<String, LoadError> {
+        let template = self.get(name)?;
+        let scope = scope.with_loader(self);
+
+        template
+            .format_in(&scope)
+            .map_err(|source| LoadError::Render {
+                name: name.to_string(),
+                source,
+            })
+    }
+
+    /// Loads a template, tracking the templates being loaded in `stack`.
+    fn load(&self, name: &str, stack: &mut Vec<String>) -> Result<Arc<Template<O, C>>, LoadError> {
+        if let Some(template) = self.cached(name) {
+            return Ok(template);
+        }
+
+        if let Some(start) = stack.iter().position(|loading| loading == name) {
+            let mut chain = stack[start..].to_vec();
+            chain.push(name.to_string());
+
+            return Err(LoadError::Cycle { chain });
+        }
+
+        let source = self
+            .source
+            .load(name)
+            .map_err(|source| LoadError::Io {
+                name: name.to_string(),
+                source,
+            })?
+            .ok_or_else(|| LoadError::NotFound {
+                name: name.to_string(),
+            })?;
+
+        let mut template = Template::compile_named(self.parser.as_ref(), &source, Some(name))
+            .map_err(|source| LoadError::Compile {
+                name: name.to_string(),
+                source,
+            })?;
+
+        stack.push(name.to_string());
+
+        for include in template.includes() {
+            self.load(include, stack)?;
+        }
+
+        let imports: Vec<String> = template.imports().map(str::to_string).collect();
+
+        for import in imports {
+            let imported = self.load(&import, stack)?;
+            template.import_macros(&imported);
+        }
+
+        let template = match template.extends() {
+            Some(parent) => Template::extend(&*self.load(parent, stack)?, &template),
+            None => template,
+        };
+
+        stack.pop();
+
+        let template = Arc::new(template);
+
+        self.cache
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(name.to_string(), template.clone());
+
+        Ok(template)
+    }
+
+    fn cached(&self, name: &str) -> Option<Arc<Template<O, C>>> {
+        self.cache
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(name)
+            .cloned()
+    }
+}
+
+impl<const O: char, const C: char> IncludeResolver for Loader<O, C> {
+    fn include(&self, name: &str, scope: &Scope) -> Result<String, DirectiveError> {
+        let template = self.get(name).map_err(|err| DirectiveError::Include {
+            name: name.to_string(),
+            reason: err.to_string(),
+        })?;
+
+        template.format_in(scope)
+    }
+}
@@ -0,0 +1,280 @@
+//! Locale conventions for formatting numbers.
+//!
+//! A [`Locale`] is set on the [`Environment`](crate::Environment) and used by
+//! format specs with grouping (`,`), currency (`$`) or percent (`%`), e.g.
+//! `{revenue | ,.2$}` renders `$1,250,000.00` in en-US and `1.250.000,00 €` in
+//! de-DE. The locale's [`PluralRule`] picks the form used by plural directives.
+
+use std::borrow::Cow;
+
+/// Separators and symbols used to format numbers.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{Context, Environment, Locale, Template, Value};
+///
+/// let tmpl = Template::<'{', '}'>::compile("{revenue | ,.2$}").unwrap();
+///
+/// let mut ctx = Context::new();
+/// ctx.insert("revenue", Value::Int(1_250_000));
+///
+/// let mut env = Environment::new();
+/// assert_eq!(tmpl.format_with(&ctx, &env).unwrap(), "$1,250,000.00");
+///
+/// env.set_locale(Locale::DE_DE);
+/// assert_eq!(tmpl.format_with(&ctx, &env).unwrap(), "1.250.000,00\u{a0}€");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// Separator between the integer and fractional parts
+    pub decimal: char,
+    /// Separator between groups of three digits
+    pub group: char,
+    /// Text written before currency amounts, after the sign
+    pub currency_prefix: Cow<'static, str>,
+    /// Text written after currency amounts
+    pub currency_suffix: Cow<'static, str>,
+    /// Text written after percentages
+    pub percent_suffix: Cow<'static, str>,
+    /// How counts map to plural categories
+    pub plural: PluralRule,
+}
+
+impl Locale {
+    /// English (United States): `$1,250,000.00`, `12.5%`
+    pub const EN_US: Self = Self {
+        decimal: '.',
+        group: ',',
+        currency_prefix: Cow::Borrowed("$"),
+        currency_suffix: Cow::Borrowed(""),
+        percent_suffix: Cow::Borrowed("%"),
+        plural: PluralRule::OneOther,
+    };
+
+    /// English (United Kingdom): `£1,250,000.00`, `12.5%`
+    pub const EN_GB: Self = Self {
+        decimal: '.',
+        group: ',',
+        currency_prefix: Cow::Borrowed("£"),
+        currency_suffix: Cow::Borrowed(""),
+        percent_suffix: Cow::Borrowed("%"),
+        plural: PluralRule::OneOther,
+    };
+
+    /// German (Germany): `1.250.000,00 €`, `12,5 %`
+    pub const DE_DE: Self = Self {
+        decimal: ',',
+        group: '.',
+        currency_prefix: Cow::Borrowed(""),
+        currency_suffix: Cow::Borrowed("\u{a0}€"),
+        percent_suffix: Cow::Borrowed("\u{a0}%"),
+        plural: PluralRule::OneOther,
+    };
+
+    /// French (France): `1 250 000,00 €`, `12,5 %`
+    pub const FR_FR: Self = Self {
+        decimal: ',',
+        group: '\u{202f}',
+        currency_prefix: Cow::Borrowed(""),
+        currency_suffix: Cow::Borrowed("\u{a0}€"),
+        percent_suffix: Cow::Borrowed("\u{a0}%"),
+        plural: PluralRule::French,
+    };
+
+    /// Looks up a built-in locale by its language tag, e.g. `"de-DE"`.
+    ///
+    /// Both `-` and `_` are accepted as separators and case is ignored.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag.replace('_', "-").to_ascii_lowercase().as_str() {
+            "en-us" | "en" => Some(Self::EN_US),
+            "en-gb" => Some(Self::EN_GB),
+            "de-de" | "de" => Some(Self::DE_DE),
+            "fr-fr" | "fr" => Some(Self::FR_FR),
+            _ => None,
+        }
+    }
+
+    /// Rewrites a number written with `.` as decimal separator in this locale.
+    ///
+    /// `digits` must be unsigned. With `grouping`, the integer part is split
+    /// into groups of three digits. Numbers in scientific notation are not
+    /// grouped.
+    pub(crate) fn localize(&self, digits: &str, grouping: bool) -> String {
+        let (int, frac) = match digits.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (digits, None),
+        };
+
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+
+        if grouping && !digits.contains(['e', 'E']) {
+            for (i, ch) in int.chars().enumerate() {
+                if i > 0 && (int.len() - i) % 3 == 0 {
+                    out.push(self.group);
+                }
+
+                out.push(ch);
+            }
+        } else {
+            out.push_str(int);
+        }
+
+        if let Some(frac) = frac {
+            out.push(self.decimal);
+            out.push_str(frac);
+        }
+
+        out
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::EN_US
+    }
+}
+
+/// A CLDR plural category.
+///
+/// Which categories a language uses, and for which numbers, is decided by its
+/// [`PluralRule`]. Every language uses `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PluralCategory {
+    /// `zero`
+    Zero,
+    /// `one`
+    One,
+    /// `two`
+    Two,
+    /// `few`
+    Few,
+    /// `many`
+    Many,
+    /// `other`
+    Other,
+}
+
+impl PluralCategory {
+    /// Parses a category name as written in templates, e.g. `"few"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zero" => Some(Self::Zero),
+            "one" => Some(Self::One),
+            "two" => Some(Self::Two),
+            "few" => Some(Self::Few),
+            "many" => Some(Self::Many),
+            "other" => Some(Self::Other),
+            _ => None,
+        }
+    }
+}
+
+/// The CLDR plural rules for integers, grouped by the languages sharing them.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{PluralCategory, PluralRule};
+///
+/// assert_eq!(PluralRule::OneOther.category(1), PluralCategory::One);
+/// assert_eq!(PluralRule::EastSlavic.category(22), PluralCategory::Few);
+/// assert_eq!(PluralRule::for_language("pl"), Some(PluralRule::Polish));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluralRule {
+    /// A single form (Chinese, Japanese, Korean, Indonesian, ...)
+    Invariant,
+    /// `one` for 1 (English, German, Dutch, Italian, Spanish, Swedish,
+    /// European Portuguese, ...)
+    OneOther,
+    /// `one` for 0 and 1, `many` for multiples of a million (French,
+    /// Brazilian Portuguese)
+    French,
+    /// `one`, `few`, `many` by the last digits (Russian, Ukrainian, Belarusian)
+    EastSlavic,
+    /// `one` for 1, `few`, `many` by the last digits (Polish)
+    Polish,
+    /// `one` for 1, `few` for 2 to 4 (Czech, Slovak)
+    Czech,
+    /// All six categories (Arabic)
+    Arabic,
+}
+
+impl PluralRule {
+    /// Returns the rule for a language code, e.g. `"ru"` or `"pt-BR"`.
+    ///
+    /// Only the language part of the tag is used, except for European
+    /// Portuguese (`pt-PT`), which differs from the Brazilian rule CLDR uses
+    /// for `pt` by treating 0 as `other`.
+    pub fn for_language(tag: &str) -> Option<Self> {
+        let mut parts = tag.split(['-', '_']);
+        let language = parts.next()?.to_ascii_lowercase();
+
+        if language == "pt"
+            && parts
+                .next()
+                .is_some_and(|region| region.eq_ignore_ascii_case("pt"))
+        {
+            return Some(Self::OneOther);
+        }
+
+        match language.as_str() {
+            "zh" | "ja" | "ko" | "id" | "ms" | "th" | "vi" => Some(Self::Invariant),
+            "en" | "de" | "nl" | "it" | "es" | "sv" | "da" | "nb" | "no" | "fi" | "el" | "hu"
+            | "et" | "bg" | "tr" => Some(Self::OneOther),
+            "fr" | "pt" => Some(Self::French),
+            "ru" | "uk" | "be" => Some(Self::EastSlavic),
+            "pl" => Some(Self::Polish),
+            "cs" | "sk" => Some(Self::Czech),
+            "ar" => Some(Self::Arabic),
+            _ => None,
+        }
+    }
+
+    /// Returns the plural category of an integer count.
+    ///
+    /// The sign is ignored, so `-1` is treated like `1`.
+    pub fn category(self, count: i64) -> PluralCategory {
+        use PluralCategory::*;
+
+        let n = count.unsigned_abs();
+        let (n10, n100) = (n % 10, n % 100);
+
+        match self {
+            Self::Invariant => Other,
+            Self::OneOther => match n {
+                1 => One,
+                _ => Other,
+            },
+            Self::French => match n {
+                0 | 1 => One,
+                _ if n.is_multiple_of(1_000_000) => Many,
+                _ => Other,
+            },
+            Self::EastSlavic => match (n10, n100) {
+                (1, _) if n100 != 11 => One,
+                (2..=4, _) if !(12..=14).contains(&n100) => Few,
+                _ => Many,
+            },
+            Self::Polish => match (n10, n100) {
+                _ if n == 1 => One,
+                (2..=4, _) if !(12..=14).contains(&n100) => Few,
+                _ => Many,
+            },
+            Self::Czech => match n {
+                1 => One,
+                2..=4 => Few,
+                _ => Other,
+            },
+            Self::Arabic => match (n, n100) {
+                (0, _) => Zero,
+                (1, _) => One,
+                (2, _) => Two,
+                (_, 3..=10) => Few,
+                (_, 11..=99) => Many,
+                _ => Other,
+            },
+        }
+    }
+}
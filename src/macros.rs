@@ -0,0 +1,65 @@
+//! Macros defined inside templates.
+//!
+//! `{macro badge(label, color)}...{endmacro}` defines a fragment that the rest
+//! of the template calls like a function, `{badge('new', 'green')}`. Arguments
+//! are bound as [`Locals`] over the context while the body renders. Macros of
+//! other templates are brought in with `{import name}` through a
+//! [`Loader`](crate::Loader).
+
+use std::{borrow::Cow, collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    Value,
+    env::{Locals, Scope},
+    err::DirectiveError,
+    fragment::Fragment,
+};
+
+/// The macros a template can call, by name.
+pub(crate) type Macros = HashMap<Box<str>, Arc<Macro>>;
+
+/// A named fragment with parameters.
+pub(crate) struct Macro {
+    pub(crate) name: Box<str>,
+    pub(crate) params: Vec<Cow<'static, str>>,
+    pub(crate) body: Fragment,
+}
+
+impl fmt::Debug for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Macro")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl Macro {
+    /// Renders the body with the parameters bound to `args`.
+    ///
+    /// The body sees the arguments and the context, but not the locals of the
+    /// caller. Its output is escaped already and returned as [`Value::Safe`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `DirectiveError::ArityMismatch` if the number of arguments does
+    /// not match the parameters, `DirectiveError::LimitExceeded` if calls are
+    /// nested too deeply, or any error raised by the body.
+    pub(crate) fn call(&self, args: Vec<Value>, scope: &Scope) -> Result<Value, DirectiveError> {
+        if args.len() != self.params.len() {
+            return Err(DirectiveError::ArityMismatch {
+                function: self.name.to_string(),
+                expected: self.params.len(),
+                found: args.len(),
+            });
+        }
+
+        let scope = scope.nested()?;
+        let locals = Locals::new(self.params.iter().cloned().zip(args).collect());
+        let ctx = scope.context();
+
+        self.body
+            .render(&scope.with_variables(ctx.with_locals(&locals)))
+            .map(|output| Value::Safe(Cow::Owned(output)))
+    }
+}
@@ -1,17 +1,22 @@
 //! Parsing template expressions into executable directives.
 //!
 //! This module provides the parsing layer that converts tokenized template
-//! expressions into executable directives. The parser uses pattern matching
-//! on token sequences to recognize different template syntaxes.
+//! expressions into executable directives. The default parser reads operands
+//! (variables, literals and function calls) and recognizes the different
+//! template syntaxes by the tokens around them.
 
 use std::borrow::Cow;
 
 use crate::{
-    arg::{Argument, ComparisonOp},
+    Value,
+    arg::{Argument, ArithmeticOp, ComparisonOp, Resolvable},
     directive::{
-        ConditionalDirective, Directive, EmptyDirective, RepeatDirective, ReplaceDirective,
+        ConditionalDirective, Directive, EmptyDirective, PluralDirective, PluralSelector,
+        RepeatDirective, ReplaceDirective,
     },
+    err::SyntaxError,
     lexer::Token,
+    locale::PluralCategory,
 };
 
 /// A parser that converts token sequences into executable directives.
@@ -20,7 +25,10 @@ use crate::{
 /// patterns and building the corresponding directive objects. Different parser
 /// implementations can support different template syntaxes.
 ///
-/// Note: This parser never return `None`, it just returns an empty directive which does nothring.
+/// Note: The default parser turns directives it does not recognize into an empty
+/// directive which does nothing; it returns an error for malformed operands,
+/// out-of-range numbers, invalid repeat counts and malformed plurals (see
+/// [`DefaultParser::parse`](DefaultParser#method.parse)).
 ///
 /// # Examples
 ///
@@ -30,18 +38,21 @@ use crate::{
 /// - `{cond ? true : false}` → Conditional directive
 ///
 /// Custom parsers can be implemented to support alternative syntaxes or
-/// additional features.
+/// additional features. Parsers are used by reference, so they can carry
+/// configuration (registered functions, strictness, locale, ...) and be
+/// handed to [`Template::compile_with`](crate::Template::compile_with).
 pub trait Parser {
     /// Parses a token sequence into a directive.
     ///
     /// # Arguments
     ///
+    /// * `self` - The parser instance, with any configuration it carries
     /// * `tokens` - A slice of tokens to parse
     ///
     /// # Returns
     ///
-    /// * `Some(Box<dyn Directive>)` - Successfully parsed directive
-    /// * `None` - Parse failed (invalid syntax)
+    /// * `Ok(Box<dyn Directive>)` - Successfully parsed directive
+    /// * `Err(SyntaxError)` - Parse failed, with the reason and the offending token
     ///
     /// # Examples
     ///
@@ -49,10 +60,93 @@ pub trait Parser {
     /// use figura::{DefaultParser, Parser, TemplateLexer};
     ///
     /// let tokens: Vec<_> = TemplateLexer::new("name").collect();
-    /// let directive = DefaultParser::parse(&tokens);
-    /// assert!(directive.is_some());
+    /// let directive = DefaultParser.parse(&tokens);
+    /// assert!(directive.is_ok());
     /// ```
-    fn parse(tokens: &[Token]) -> Option<Box<dyn Directive + Send + Sync>>;
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError>;
+
+    /// Returns whether the directive made of `tokens` ends in a format spec
+    /// after its first `|`, as in `{price | >10.2}`.
+    ///
+    /// If so, the template compiler hands only the tokens before the `|` to
+    /// [`Parser::parse`] and formats the directive's value with the spec.
+    /// Defaults to `false`, so a parser giving `|` a meaning of its own
+    /// receives the whole directive. [`DefaultParser`] returns `true`.
+    fn splits_format_spec(&self, _tokens: &[Token]) -> bool {
+        false
+    }
+
+    /// Chains this parser with a fallback parser.
+    ///
+    /// The returned parser tries `self` first and hands the directive to
+    /// `fallback` only when `self` reports it as unrecognized
+    /// (see [`SyntaxError::unrecognized`]). Any other error is returned as-is.
+    ///
+    /// Since [`DefaultParser`] accepts every directive, it belongs at the end of a chain.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use figura::{DefaultParser, Parser, Template};
+    ///
+    /// # #[derive(Default)] struct MathParser;
+    /// # impl Parser for MathParser {
+    /// #     fn parse(&self, _: &[figura::Token]) -> Result<Box<dyn figura::Directive + Send + Sync>, figura::SyntaxError> {
+    /// #         Err(figura::SyntaxError::unrecognized())
+    /// #     }
+    /// # }
+    /// let parser = MathParser.or(DefaultParser);
+    /// let tmpl = Template::<'{', '}'>::compile_with(&parser, "{x + y} {name}").unwrap();
+    /// ```
+    fn or<P: Parser>(self, fallback: P) -> Chain<Self, P>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: fallback,
+        }
+    }
+}
+
+impl<P: Parser + ?Sized> Parser for &P {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        (**self).parse(tokens)
+    }
+
+    fn splits_format_spec(&self, tokens: &[Token]) -> bool {
+        (**self).splits_format_spec(tokens)
+    }
+}
+
+/// Two parsers chained together, created by [`Parser::or`].
+///
+/// Directives are handed to `first`; if it reports them as unrecognized,
+/// they are handed to `second`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Chain<A, B> {
+    /// The parser tried first
+    pub first: A,
+    /// The parser used when `first` does not recognize a directive
+    pub second: B,
+}
+
+impl<A: Parser, B: Parser> Parser for Chain<A, B> {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        match self.first.parse(tokens) {
+            Err(err) if err.is_unrecognized() => self.second.parse(tokens),
+            result => result,
+        }
+    }
+
+    /// Asks the parser that handles the whole directive, so `first` still
+    /// receives `|` when it recognizes it.
+    fn splits_format_spec(&self, tokens: &[Token]) -> bool {
+        match self.first.parse(tokens) {
+            Err(err) if err.is_unrecognized() => self.second.splits_format_spec(tokens),
+            _ => self.first.splits_format_spec(tokens),
+        }
+    }
 }
 
 /// The default parser implementation.
@@ -64,6 +158,8 @@ pub trait Parser {
 /// - **Simple conditionals**: `{condition ? true_value : false_value}`
 /// - **Comparison conditionals**: `{x == 5 ? "yes" : "no"}`
 /// - **Logical NOT**: `{!active ? "inactive" : "active"}`
+/// - **Function calls**: `{max(a, b)}` - Calls a function registered on the `Environment`
+/// - **Plurals**: `{plural n one: '# item', other: '# items'}` - Picks a form by count
 ///
 /// Supported comparison operators: `==`, `!=`, `>`, `<`, `>=`, `<=`
 ///
@@ -73,20 +169,24 @@ pub trait Parser {
 /// use figura::{Template, DefaultParser};
 ///
 /// // Variable substitution
-/// let t1 = Template::<'{', '}'>::compile::<DefaultParser>("{name}").unwrap();
+/// let t1 = Template::<'{', '}'>::compile_with_parser::<DefaultParser>("{name}").unwrap();
 ///
 /// // Repeat pattern
-/// let t2 = Template::<'{', '}'>::compile::<DefaultParser>("{'*':3}").unwrap();
+/// let t2 = Template::<'{', '}'>::compile_with_parser::<DefaultParser>("{'*':3}").unwrap();
 ///
 /// // Conditional with comparison
-/// let t3 = Template::<'{', '}'>::compile::<DefaultParser>(
+/// let t3 = Template::<'{', '}'>::compile_with_parser::<DefaultParser>(
 ///     "{age >= 18 ? 'adult' : 'minor'}"
 /// ).unwrap();
 /// ```
+#[derive(Debug, Default, Clone, Copy)]
 pub struct DefaultParser;
 
 /// Converts a token into an argument for use in directives.
 ///
+/// This is the helper `DefaultParser` uses for every operand, exposed so
+/// custom parsers can build the same arguments.
+///
 /// # Arguments
 ///
 /// * `token` - The token to convert
@@ -94,173 +194,599 @@ pub struct DefaultParser;
 /// # Returns
 ///
 /// An `Argument` representing the token's value:
-/// - `Ident` → Variable argument
-/// - `Literal`, `Int`, `Float` → Literal argument
+/// - `Ident` → Variable argument, except `true`, `false` and `null` which are literals
+/// - `Literal` → String literal
+/// - `Int`, `Float` → Integer or float literal (`0xff` → `255`, `1_000` → `1000`);
+///   numbers out of range are kept as string literals
 /// - Other tokens → Empty literal
-fn token_to_argument(token: &Token) -> Argument {
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{token_to_argument, Argument, Token};
+///
+/// let arg = token_to_argument(&Token::Ident("name"));
+/// assert!(matches!(arg, Argument::Variable(_)));
+/// ```
+pub fn token_to_argument(token: &Token) -> Argument {
     match token {
+        Token::Ident("true") => Argument::literal(true),
+        Token::Ident("false") => Argument::literal(false),
+        Token::Ident("null") => Argument::literal(Value::Null),
         Token::Ident(s) => Argument::variable(Cow::Owned(s.to_string())),
         Token::Literal(s) => Argument::literal(Cow::Owned(s.to_string())),
-        Token::Int(s) => Argument::literal(Cow::Owned(s.to_string())),
-        Token::Float(s) => Argument::literal(Cow::Owned(s.to_string())),
-        _ => Argument::literal(Cow::Borrowed("")),
+        Token::Int(_) | Token::Float(_) => number_literal(token, false)
+            .unwrap_or_else(|| Argument::literal(token_text(token).to_string())),
+        _ => Argument::literal(""),
     }
 }
 
-impl Parser for DefaultParser {
-    /// Parses tokens into directives using pattern matching.
-    ///
-    /// This implementation matches token sequences against known patterns
-    /// and constructs the appropriate directive type. Patterns are matched
-    /// in order, with more specific patterns checked first.
-    ///
-    /// # Supported Patterns
-    ///
-    /// 1. **Variable replacement**: `[Ident]` → `{name}`
-    /// 2. **Literal value**: `[Literal]` → `{"text"}`
-    /// 3. **Repeat pattern**: `[Pattern, Colon, Count]` → `{pattern:count}`
-    /// 4. **Simple conditional**: `[Cond, Question, True, Colon, False]` → `{cond ? true : false}`
-    /// 5. **Comparison conditional**: `[Left, Op, Right, Question, True, Colon, False]` → `{x == 5 ? yes : no}`
-    /// 6. **NOT conditional**: `[Not, Cond, Question, True, Colon, False]` → `{!cond ? yes : no}`
-    ///
-    /// # Returns
-    ///
-    /// Returns `Some(directive)` if parsing succeeds, or `Some(EmptyDirective)` if
-    /// the token sequence doesn't match any known pattern. Returns `None` only if
-    /// a critical parsing error occurs (currently never happens in practice).
-    fn parse(tokens: &[Token]) -> Option<Box<dyn Directive + Send + Sync>> {
-        match tokens {
-            // Simple variable replacement: {name}
-            // Example: {username} → ReplaceDirective(Variable("username"))
-            [Token::Ident(ident)] => Some(Box::new(ReplaceDirective(Argument::variable(
-                Cow::Owned(ident.to_string()),
-            )))),
+/// Returns the source text of a numeric token.
+fn token_text<'a>(token: &Token<'a>) -> &'a str {
+    match token {
+        Token::Int(s) | Token::Float(s) => s,
+        _ => "",
+    }
+}
+
+/// Parses a numeric token, optionally negated, into a literal argument.
+///
+/// Returns `None` if the number does not fit an `i64` or a finite `f64`.
+fn number_literal(token: &Token, negative: bool) -> Option<Argument> {
+    let value = match token {
+        Token::Int(s) => {
+            let digits = s.replace('_', "");
+
+            let magnitude = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+                None => digits.parse::<u64>().ok()?,
+            };
+
+            let value = if negative {
+                -i128::from(magnitude)
+            } else {
+                i128::from(magnitude)
+            };
+
+            Value::Int(i64::try_from(value).ok()?)
+        }
+        Token::Float(s) => {
+            let value = s.replace('_', "").parse::<f64>().ok()?;
+
+            if !value.is_finite() {
+                return None;
+            }
+
+            Value::Float(if negative { -value } else { value })
+        }
+        _ => return None,
+    };
+
+    Some(Argument::literal(value))
+}
+
+/// Converts a comparison token into its operator.
+///
+/// # Returns
+///
+/// `Some(ComparisonOp)` for `==`, `!=`, `>`, `<`, `>=` and `<=`, `None` for any other token.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{comparison_op, Token};
+///
+/// assert!(comparison_op(&Token::GreaterThanEquals).is_some());
+/// assert!(comparison_op(&Token::Plus).is_none());
+/// ```
+pub fn comparison_op(token: &Token) -> Option<ComparisonOp> {
+    match token {
+        Token::Equals => Some(ComparisonOp::Equals),
+        Token::NotEquals => Some(ComparisonOp::NotEquals),
+        Token::GreaterThan => Some(ComparisonOp::GreaterThan),
+        Token::LessThan => Some(ComparisonOp::LessThan),
+        Token::GreaterThanEquals => Some(ComparisonOp::GreaterThanEquals),
+        Token::LessThanEquals => Some(ComparisonOp::LessThanEquals),
+        _ => None,
+    }
+}
+
+/// Parses a single operand starting at `*pos`.
+///
+/// An operand is a variable, a string or numeric literal, or a function call
+/// whose arguments are themselves operands: `name`, `'text'`, `42`, `-1.5e3`,
+/// `max(a, 1)`. Numbers may carry a leading `-` or `+` sign. Operands can be
+/// combined with `+`, `-`, `*` and `/`, with the usual precedence and
+/// parentheses for grouping: `(price + fee) * qty`. They can be chained with
+/// `??` to fall back on missing or null values, which binds loosest:
+/// `nickname ?? name ?? 'anonymous'`.
+/// This is the helper `DefaultParser` uses for every operand, exposed so custom
+/// parsers can accept the same operands.
+///
+/// # Arguments
+///
+/// * `tokens` - The tokens of the whole directive
+/// * `pos` - The index of the first token of the operand; advanced past it on success
+///
+/// # Returns
+///
+/// * `Ok(Some(argument))` - The parsed operand
+/// * `Ok(None)` - The token at `pos` cannot start an operand; `pos` is unchanged
+/// * `Err(SyntaxError)` - A function call is malformed, a number is out of range,
+///   a parenthesis is not closed, an operator is not followed by an operand or
+///   operands nest more than 128 levels deep. The error points at the offending
+///   token, or one past the last token if the operand is incomplete.
+///
+/// # Examples
+///
+/// ```rust
+/// use figura::{parse_operand, TemplateLexer};
+///
+/// let tokens: Vec<_> = TemplateLexer::new("max(a, 1) > 3").collect();
+/// let mut pos = 0;
+///
+/// let arg = parse_operand(&tokens, &mut pos).unwrap();
+/// assert!(arg.is_some());
+/// assert_eq!(pos, 6);
+/// ```
+pub fn parse_operand(tokens: &[Token], pos: &mut usize) -> Result<Option<Argument>, SyntaxError> {
+    operand(tokens, pos, 0)
+}
+
+/// How deeply operands can nest through parentheses, calls, signs and
+/// operators; parsing stops there instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Returns the depth of an operand nested inside one at `depth`, failing at
+/// the token at `pos` past [`MAX_DEPTH`].
+fn nested(pos: usize, depth: usize) -> Result<usize, SyntaxError> {
+    if depth >= MAX_DEPTH {
+        return Err(SyntaxError::at(
+            pos,
+            format!("operands cannot nest more than {} levels deep", MAX_DEPTH),
+        ));
+    }
+
+    Ok(depth + 1)
+}
+
+/// Parses an operand nested `depth` levels inside the directive.
+fn operand(
+    tokens: &[Token],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<Option<Argument>, SyntaxError> {
+    let Some(first) = parse_sum(tokens, pos, depth)? else {
+        return Ok(None);
+    };
+
+    if tokens.get(*pos) != Some(&Token::Coalesce) {
+        return Ok(Some(first));
+    }
+
+    let mut args = vec![first];
+
+    while tokens.get(*pos) == Some(&Token::Coalesce) {
+        *pos += 1;
+
+        let Some(arg) = parse_sum(tokens, pos, depth)? else {
+            return Err(SyntaxError::at(*pos, "expected an operand after '??'"));
+        };
+
+        args.push(arg);
+    }
+
+    Ok(Some(Argument::coalesce(args)))
+}
+
+/// Parses operands joined by `+` and `-`.
+fn parse_sum(
+    tokens: &[Token],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<Option<Argument>, SyntaxError> {
+    parse_binary(tokens, pos, depth, parse_product, |token| match token {
+        Token::Plus => Some(ArithmeticOp::Add),
+        Token::Minus => Some(ArithmeticOp::Subtract),
+        _ => None,
+    })
+}
+
+/// Parses operands joined by `*` and `/`.
+fn parse_product(
+    tokens: &[Token],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<Option<Argument>, SyntaxError> {
+    parse_binary(tokens, pos, depth, parse_primary, |token| match token {
+        Token::Star => Some(ArithmeticOp::Multiply),
+        Token::Slash => Some(ArithmeticOp::Divide),
+        _ => None,
+    })
+}
+
+/// Parses an operand at a given nesting depth.
+type OperandParser = fn(&[Token], &mut usize, usize) -> Result<Option<Argument>, SyntaxError>;
+
+/// Parses a left-associative chain of `operand` joined by the operators `op` accepts.
+///
+/// Each operator nests the chain parsed so far one level deeper.
+fn parse_binary(
+    tokens: &[Token],
+    pos: &mut usize,
+    mut depth: usize,
+    operand: OperandParser,
+    op: fn(&Token) -> Option<ArithmeticOp>,
+) -> Result<Option<Argument>, SyntaxError> {
+    let Some(mut left) = operand(tokens, pos, depth)? else {
+        return Ok(None);
+    };
+
+    while let Some(op) = tokens.get(*pos).and_then(op) {
+        depth = nested(*pos, depth)?;
+        *pos += 1;
+
+        let Some(right) = operand(tokens, pos, depth)? else {
+            return Err(SyntaxError::at(
+                *pos,
+                format!("expected an operand after '{}'", op.symbol()),
+            ));
+        };
+
+        left = Argument::arithmetic(left, op, right);
+    }
+
+    Ok(Some(left))
+}
+
+/// Parses an operand without operators, or a parenthesized operand.
+fn parse_primary(
+    tokens: &[Token],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<Option<Argument>, SyntaxError> {
+    let argument = match tokens.get(*pos) {
+        Some(Token::LParen) => {
+            let depth = nested(*pos, depth)?;
+            *pos += 1;
+
+            let Some(inner) = operand(tokens, pos, depth)? else {
+                return Err(SyntaxError::at(*pos, "expected an operand after '('"));
+            };
+
+            if tokens.get(*pos) != Some(&Token::RParen) {
+                return Err(SyntaxError::at(*pos, "expected ')'"));
+            }
+
+            *pos += 1;
+            return Ok(Some(inner));
+        }
+
+        Some(Token::Ident(name)) if tokens.get(*pos + 1) == Some(&Token::LParen) => {
+            let depth = nested(*pos, depth)?;
+            *pos += 2;
+
+            let mut args = Vec::new();
+
+            if tokens.get(*pos) == Some(&Token::RParen) {
+                *pos += 1;
+            } else {
+                loop {
+                    let Some(arg) = operand(tokens, pos, depth)? else {
+                        return Err(SyntaxError::at(*pos, "expected a function argument"));
+                    };
+
+                    args.push(arg);
+
+                    match tokens.get(*pos) {
+                        Some(Token::Comma) => *pos += 1,
+                        Some(Token::RParen) => {
+                            *pos += 1;
+                            break;
+                        }
+                        _ => {
+                            return Err(SyntaxError::at(
+                                *pos,
+                                format!("expected ',' or ')' in the call to '{}'", name),
+                            ));
+                        }
+                    }
+                }
+            }
+
+            return Ok(Some(Argument::call(Cow::Owned(name.to_string()), args)));
+        }
+
+        Some(sign @ (Token::Minus | Token::Plus))
+            if matches!(tokens.get(*pos + 1), Some(Token::Int(_) | Token::Float(_))) =>
+        {
+            *pos += 1;
+            number(tokens, *pos, sign == &Token::Minus)?
+        }
+
+        Some(sign @ (Token::Minus | Token::Plus)) => {
+            let depth = nested(*pos, depth)?;
+            *pos += 1;
+
+            let Some(operand) = parse_primary(tokens, pos, depth)? else {
+                let symbol = if sign == &Token::Minus { '-' } else { '+' };
+                return Err(SyntaxError::at(
+                    *pos,
+                    format!("expected an operand after '{}'", symbol),
+                ));
+            };
+
+            // Negating a variable, call or group is spelled as `0 - operand`.
+            return Ok(Some(match sign {
+                Token::Minus => Argument::arithmetic(
+                    Argument::literal(Value::Int(0)),
+                    ArithmeticOp::Subtract,
+                    operand,
+                ),
+                _ => operand,
+            }));
+        }
+
+        Some(Token::Int(_) | Token::Float(_)) => number(tokens, *pos, false)?,
+
+        Some(token @ (Token::Ident(_) | Token::Literal(_))) => token_to_argument(token),
+
+        _ => return Ok(None),
+    };
+
+    *pos += 1;
+    Ok(Some(argument))
+}
+
+/// Parses the numeric token at `pos`, failing if it is out of range.
+fn number(tokens: &[Token], pos: usize, negative: bool) -> Result<Argument, SyntaxError> {
+    number_literal(&tokens[pos], negative).ok_or_else(|| {
+        SyntaxError::at(
+            pos,
+            format!("number '{}' is out of range", token_text(&tokens[pos])),
+        )
+    })
+}
+
+impl DefaultParser {
+    /// Parses a directive, returning `None` if it matches no known shape.
+    fn parse_directive(
+        tokens: &[Token],
+    ) -> Result<Option<Box<dyn Directive + Send + Sync>>, SyntaxError> {
+        if let Some(directive) = Self::parse_plural(tokens)? {
+            return Ok(Some(directive));
+        }
+
+        let mut pos = 0;
+
+        // Condition: [!] operand [op operand]
+        let negated = tokens.first() == Some(&Token::Not);
+
+        if negated {
+            pos += 1;
+        }
+
+        let Some(left) = parse_operand(tokens, &mut pos)? else {
+            return Ok(None);
+        };
+
+        let op = tokens.get(pos).and_then(comparison_op);
+        let plain = !negated && op.is_none();
+
+        let mut cond = match op {
+            Some(op) => {
+                pos += 1;
+
+                let Some(right) = parse_operand(tokens, &mut pos)? else {
+                    return Ok(None);
+                };
+
+                Argument::comparison(left, op, right)
+            }
+            None => left,
+        };
+
+        if negated {
+            cond = Argument::not(cond);
+        }
+
+        match tokens.get(pos) {
+            // Replacement: {name}, {"text"}, {max(a, b)}
+            None => Ok(Some(Box::new(ReplaceDirective(cond)))),
 
             // Repeat pattern: {pattern:count}
             // Examples:
-            //   {'*':3} → RepeatDirective(Literal("*"), Literal("3"))
+            //   {'*':3} → RepeatDirective(Literal("*"), Literal(3))
             //   {char:n} → RepeatDirective(Variable("char"), Variable("n"))
-            [
-                p @ (Token::Ident(_) | Token::Literal(_)),
-                Token::Colon,
-                c @ (Token::Ident(_) | Token::Int(_)),
-            ] => {
-                let pattern = match p {
-                    Token::Ident(s) => Argument::variable(Cow::Owned(s.to_string())),
-                    Token::Literal(cow) => Argument::literal(Cow::Owned(cow.to_string())),
-                    _ => unreachable!(),
-                };
+            Some(Token::Colon) if plain => {
+                pos += 1;
+                let count_pos = pos;
 
-                let count = match c {
-                    Token::Ident(s) => Argument::variable(Cow::Owned(s.to_string())),
-                    Token::Int(s) => Argument::literal(Cow::Owned(s.to_string())),
-                    _ => unreachable!(),
+                let Some(count) = parse_operand(tokens, &mut pos)? else {
+                    return Ok(None);
                 };
 
-                Some(Box::new(RepeatDirective(pattern, count)))
-            }
+                if pos != tokens.len() {
+                    return Ok(None);
+                }
 
-            // Literal replacement: {"text"}
-            // Example: {"hello"} → ReplaceDirective(Literal("hello"))
-            [Token::Literal(lit)] => Some(Box::new(ReplaceDirective(Argument::literal(
-                Cow::Owned(lit.to_string()),
-            )))),
+                // A literal count is known now, so reject it now
+                if let Argument::Literal(value) = &count
+                    && i64::from_value(value).is_none()
+                {
+                    return Err(SyntaxError::at(
+                        count_pos,
+                        format!(
+                            "repeat count must be an integer, found {}",
+                            value.type_name()
+                        ),
+                    ));
+                }
 
-            // Ternary conditional: {condition ? if_true : if_false}
-            // The condition can be a variable, literal, or numeric value
-            // Examples:
-            //   {active ? "yes" : "no"} → ConditionalDirective with Variable("active")
-            //   {true ? "yes" : "no"} → ConditionalDirective with Literal("true")
-            [
-                cond @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-                Token::Question,
-                if_true @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-                Token::Colon,
-                if_false @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-            ] => {
-                let cond_arg = token_to_argument(cond);
-                let true_arg = token_to_argument(if_true);
-                let false_arg = token_to_argument(if_false);
-
-                Some(Box::new(ConditionalDirective {
-                    cond: cond_arg,
-                    if_true: true_arg,
-                    if_false: false_arg,
-                }))
+                Ok(Some(Box::new(RepeatDirective(cond, count))))
             }
 
-            // Conditional with comparison: {left op right ? if_true : if_false}
-            // Supports: ==, !=, >, <, >=, <=
+            // Ternary conditional: {condition ? if_true : if_false}
             // Examples:
+            //   {active ? "yes" : "no"}
             //   {age >= 18 ? "adult" : "minor"}
-            //   {status == "active" ? "online" : "offline"}
-            //   {count > 0 ? items : "empty"}
-            [
-                left @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_) | Token::Float(_)),
-                op @ (Token::Equals
-                | Token::NotEquals
-                | Token::GreaterThan
-                | Token::LessThan
-                | Token::GreaterThanEquals
-                | Token::LessThanEquals),
-                right @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_) | Token::Float(_)),
-                Token::Question,
-                if_true @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-                Token::Colon,
-                if_false @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-            ] => {
-                let left_arg = token_to_argument(left);
-                let right_arg = token_to_argument(right);
-
-                let comp_op = match op {
-                    Token::Equals => ComparisonOp::Equals,
-                    Token::NotEquals => ComparisonOp::NotEquals,
-                    Token::GreaterThan => ComparisonOp::GreaterThan,
-                    Token::LessThan => ComparisonOp::LessThan,
-                    Token::GreaterThanEquals => ComparisonOp::GreaterThanEquals,
-                    Token::LessThanEquals => ComparisonOp::LessThanEquals,
-                    _ => unreachable!(),
+            //   {!active ? "disabled" : "enabled"}
+            Some(Token::Question) => {
+                pos += 1;
+
+                let Some(if_true) = parse_operand(tokens, &mut pos)? else {
+                    return Ok(None);
+                };
+
+                if tokens.get(pos) != Some(&Token::Colon) {
+                    return Ok(None);
+                }
+
+                pos += 1;
+
+                let Some(if_false) = parse_operand(tokens, &mut pos)? else {
+                    return Ok(None);
                 };
 
-                let cond_arg = Argument::comparison(left_arg, comp_op, right_arg);
-                let true_arg = token_to_argument(if_true);
-                let false_arg = token_to_argument(if_false);
+                if pos != tokens.len() {
+                    return Ok(None);
+                }
 
-                Some(Box::new(ConditionalDirective {
-                    cond: cond_arg,
-                    if_true: true_arg,
-                    if_false: false_arg,
-                }))
+                Ok(Some(Box::new(ConditionalDirective {
+                    cond,
+                    if_true,
+                    if_false,
+                })))
             }
 
-            // Logical NOT conditional: {!condition ? if_true : if_false}
-            // Examples:
-            //   {!active ? "disabled" : "enabled"}
-            //   {!0 ? "truthy" : "falsy"}
-            [
-                Token::Not,
-                cond @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-                Token::Question,
-                if_true @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-                Token::Colon,
-                if_false @ (Token::Ident(_) | Token::Literal(_) | Token::Int(_)),
-            ] => {
-                let inner_arg = token_to_argument(cond);
-                let cond_arg = Argument::not(inner_arg);
-                let true_arg = token_to_argument(if_true);
-                let false_arg = token_to_argument(if_false);
-
-                Some(Box::new(ConditionalDirective {
-                    cond: cond_arg,
-                    if_true: true_arg,
-                    if_false: false_arg,
-                }))
+            _ => Ok(None),
+        }
+    }
+}
+
+impl DefaultParser {
+    /// Parses `plural count selector: form, ...`.
+    ///
+    /// Returns `None` if the directive does not start with the `plural` keyword
+    /// followed by a count and a selector, so `{plural}` stays a variable.
+    fn parse_plural(
+        tokens: &[Token],
+    ) -> Result<Option<Box<dyn Directive + Send + Sync>>, SyntaxError> {
+        if tokens.first() != Some(&Token::Ident("plural")) {
+            return Ok(None);
+        }
+
+        let mut pos = 1;
+
+        let Some(count) = parse_operand(tokens, &mut pos)? else {
+            return Ok(None);
+        };
+
+        if !matches!(tokens.get(pos), Some(Token::Ident(_) | Token::Assign)) {
+            return Ok(None);
+        }
+
+        let mut forms = Vec::new();
+
+        while pos < tokens.len() {
+            let selector = match &tokens[pos] {
+                Token::Ident(name) => {
+                    let category = PluralCategory::from_name(name).ok_or_else(|| {
+                        SyntaxError::at(pos, format!("unknown plural category '{}'", name))
+                    })?;
+
+                    pos += 1;
+                    PluralSelector::Category(category)
+                }
+                Token::Assign => {
+                    pos += 1;
+
+                    match parse_operand(tokens, &mut pos)? {
+                        Some(Argument::Literal(Value::Int(n))) => PluralSelector::Exact(n),
+                        _ => return Err(SyntaxError::at(pos, "expected an integer after '='")),
+                    }
+                }
+                _ => {
+                    return Err(SyntaxError::at(
+                        pos,
+                        "expected a plural category or '=count'",
+                    ));
+                }
+            };
+
+            if tokens.get(pos) != Some(&Token::Colon) {
+                return Err(SyntaxError::at(
+                    pos,
+                    "expected ':' after the plural selector",
+                ));
             }
 
+            pos += 1;
+
+            let Some(form) = parse_operand(tokens, &mut pos)? else {
+                return Err(SyntaxError::at(pos, "expected a plural form"));
+            };
+
+            forms.push((selector, form));
+
+            if tokens.get(pos) == Some(&Token::Comma) {
+                pos += 1;
+            }
+        }
+
+        if !forms
+            .iter()
+            .any(|(selector, _)| *selector == PluralSelector::Category(PluralCategory::Other))
+        {
+            return Err(SyntaxError::new("plural forms must include 'other'"));
+        }
+
+        Ok(Some(Box::new(PluralDirective { count, forms })))
+    }
+}
+
+impl Parser for DefaultParser {
+    /// Parses tokens into directives.
+    ///
+    /// The directive starts with a condition, which is an operand optionally
+    /// negated with `!` or compared to a second operand. What follows the
+    /// condition selects the directive type. Operands are variables, literals,
+    /// function calls or arithmetic on them (see [`parse_operand`]).
+    ///
+    /// # Supported Patterns
+    ///
+    /// 1. **Replacement**: `[Operand]` → `{name}`, `{"text"}`, `{max(a, b)}`
+    /// 2. **Repeat pattern**: `[Pattern, Colon, Count]` → `{pattern:count}`
+    /// 3. **Simple conditional**: `[Cond, Question, True, Colon, False]` → `{cond ? true : false}`
+    /// 4. **Comparison conditional**: `[Left, Op, Right, Question, True, Colon, False]` → `{x == 5 ? yes : no}`
+    /// 5. **NOT conditional**: `[Not, Cond, Question, True, Colon, False]` → `{!cond ? yes : no}`
+    ///
+    /// A comparison or negation without a `?` renders as `true` or `false`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(directive)` if parsing succeeds, or `Ok(EmptyDirective)` if
+    /// the token sequence doesn't match any known pattern. Returns a
+    /// [`SyntaxError`] for:
+    /// - malformed operands: unbalanced parentheses, function calls missing
+    ///   `,` or `)`, or an operator without an operand after it
+    /// - number literals out of range
+    /// - non-integer literal repeat counts
+    /// - malformed plurals: an unknown category, a missing `:` or form, or no
+    ///   `other` form
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        match Self::parse_directive(tokens)? {
+            Some(directive) => Ok(directive),
+
             // Unknown pattern: return empty directive (silent failure)
             // This allows templates to compile even with unsupported expressions,
             // which will simply produce no output rather than failing to compile.
-            _ => Some(Box::new(EmptyDirective)),
+            None => Ok(Box::new(EmptyDirective)),
         }
     }
+
+    /// Format specs are always split off: `{price | >10.2}`.
+    fn splits_format_spec(&self, _tokens: &[Token]) -> bool {
+        true
+    }
 }
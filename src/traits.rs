@@ -8,6 +8,12 @@ impl ToAstring for i64 {
     }
 }
 
+impl ToAstring for u64 {
+    fn to_astring(self) -> String {
+        itoa::Buffer::new().format(self).to_owned()
+    }
+}
+
 impl ToAstring for f64 {
     fn to_astring(self) -> String {
         zmij::Buffer::new().format(self).to_owned()
@@ -1,4 +1,14 @@
-use figura::{Context, Template, Value};
+#![allow(clippy::approx_constant)]
+
+use std::borrow::Cow;
+
+use figura::{
+    Align, Argument, ArithmeticOp, ConditionalDirective, Context, Count, DefaultParser, Directive,
+    DirectiveError, DirectorySource, Environment, Escape, FormatSpec, LayeredContext, LexError,
+    Limit, Limits, LoadError, Loader, Locale, Locals, MemorySource, MissingVariable, NumberStyle,
+    Parser, PluralCategory, PluralRule, RenderError, Scope, Span, Spanned, SyntaxError, Template,
+    TemplateError, TemplateLexer, Token, Value, comparison_op, token_to_argument,
+};
 
 type CBTemplate = Template<'{', '}'>;
 type ParenTemplate = Template<'(', ')'>;
@@ -769,3 +779,3347 @@ fn test_conditional_integer_falsy_zero() {
     let result = template.format(&ctx).unwrap();
     assert_eq!(result, "Empty");
 }
+
+// ============================================
+// Raw Block Tests
+// ============================================
+
+#[test]
+fn test_raw_block_keeps_delimiters() {
+    let template = CBTemplate::compile("{raw}fn main() { println!(\"{}\", x); }{endraw}").unwrap();
+    let ctx = Context::new();
+
+    let result = template.format(&ctx).unwrap();
+    assert_eq!(result, "fn main() { println!(\"{}\", x); }");
+}
+
+#[test]
+fn test_raw_block_skips_escape_processing() {
+    let template = CBTemplate::compile("{raw}{{name}} }}{endraw}").unwrap();
+    let ctx = Context::new();
+
+    let result = template.format(&ctx).unwrap();
+    assert_eq!(result, "{{name}} }}");
+}
+
+#[test]
+fn test_raw_block_mixed_with_directives() {
+    let template = CBTemplate::compile("Hi {name}: { raw }{name}{ endraw } {name}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Bob"));
+
+    let result = template.format(&ctx).unwrap();
+    assert_eq!(result, "Hi Bob: {name} Bob");
+}
+
+#[test]
+fn test_raw_block_custom_delimiters() {
+    let template = SquareTemplate::compile("[raw][a] [[b]][endraw][name]").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("!"));
+
+    let result = template.format(&ctx).unwrap();
+    assert_eq!(result, "[a] [[b]]!");
+}
+
+#[test]
+fn test_raw_variable_needs_parentheses() {
+    let mut ctx = Context::new();
+    ctx.insert("raw", Value::static_str("r"));
+    ctx.insert("endraw", Value::static_str("e"));
+
+    // `{raw}` opens a raw block, so the variable is written `{(raw)}`
+    assert_eq!(
+        render("{(raw)} {raw == 'r' ? 1 : 2} {endraw}", &ctx),
+        "r 1 e"
+    );
+    assert_eq!(render("{raw}{(raw)}{endraw}", &ctx), "{(raw)}");
+}
+
+#[test]
+fn test_raw_block_unclosed_error() {
+    let result = CBTemplate::compile("{raw}never closed {name}");
+    assert!(matches!(result, Err(TemplateError::UnclosedRawBlock)));
+}
+
+// ============================================
+// Custom Parser Tests
+// ============================================
+
+/// A parser that upper-cases every variable it substitutes unless told otherwise.
+#[derive(Default)]
+struct ShoutParser {
+    quiet: bool,
+}
+
+struct ShoutDirective {
+    name: &'static str,
+    quiet: bool,
+}
+
+impl Directive for ShoutDirective {
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let value = Argument::variable(Cow::Borrowed(self.name)).resolve_as::<Cow<str>>(scope)?;
+
+        if self.quiet {
+            Ok(value)
+        } else {
+            Ok(Cow::Owned(value.to_uppercase()))
+        }
+    }
+}
+
+impl Parser for ShoutParser {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        match tokens {
+            [Token::Ident(name)] => Ok(Box::new(ShoutDirective {
+                name: Box::leak(name.to_string().into_boxed_str()),
+                quiet: self.quiet,
+            })),
+            [Token::Ident(_), _, ..] => Err(SyntaxError::at(1, "expected a single variable")),
+            _ => Err(SyntaxError::new("unsupported directive")),
+        }
+    }
+    fn splits_format_spec(&self, _tokens: &[Token]) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_compile_with_parser_instance() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Alice"));
+
+    let loud = CBTemplate::compile_with(&ShoutParser { quiet: false }, "Hi {name}!").unwrap();
+    let quiet = CBTemplate::compile_with(&ShoutParser { quiet: true }, "Hi {name}!").unwrap();
+
+    assert_eq!(loud.format(&ctx).unwrap(), "Hi ALICE!");
+    assert_eq!(quiet.format(&ctx).unwrap(), "Hi Alice!");
+}
+
+#[test]
+fn test_compile_with_parser_static_form() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Alice"));
+
+    let template = CBTemplate::compile_with_parser::<ShoutParser>("Hi {name}!").unwrap();
+    assert_eq!(template.format(&ctx).unwrap(), "Hi ALICE!");
+}
+
+#[test]
+fn test_compile_with_dyn_parser() {
+    let parsers: [&dyn Parser; 2] = [&DefaultParser, &ShoutParser { quiet: false }];
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Bob"));
+
+    let outputs: Vec<String> = parsers
+        .iter()
+        .map(|p| {
+            CBTemplate::compile_with(*p, "{name}")
+                .unwrap()
+                .format(&ctx)
+                .unwrap()
+        })
+        .collect();
+
+    assert_eq!(outputs, ["Bob", "BOB"]);
+}
+
+#[test]
+fn test_compile_with_parser_rejecting_directive() {
+    let result = CBTemplate::compile_with(&ShoutParser::default(), "Hi {a ? b : c}");
+
+    match result {
+        Err(TemplateError::DirectiveParsing {
+            content,
+            error,
+            span,
+        }) => {
+            assert_eq!(content, "a ? b : c");
+            assert_eq!(error, SyntaxError::at(1, "expected a single variable"));
+            assert_eq!(span, Span::new(6, 7));
+        }
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parser_error_message_is_displayed() {
+    let err = CBTemplate::compile_with(&ShoutParser::default(), "{'literal'}").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failed to parse directive ''literal'': unsupported directive"
+    );
+}
+
+/// Handles `{left + right}` and leaves everything else to the next parser.
+struct SumParser;
+
+struct SumDirective(Argument, Argument);
+
+impl Directive for SumDirective {
+    fn exec(&self, scope: &Scope) -> Result<Cow<'static, str>, DirectiveError> {
+        let sum = self.0.resolve_as::<i64>(scope)? + self.1.resolve_as::<i64>(scope)?;
+        Ok(Cow::Owned(sum.to_string()))
+    }
+}
+
+impl Parser for SumParser {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        match tokens {
+            [left @ (Token::Ident(_) | Token::Int(_)), Token::Plus, right] => match right {
+                Token::Ident(_) | Token::Int(_) => Ok(Box::new(SumDirective(
+                    token_to_argument(left),
+                    token_to_argument(right),
+                ))),
+                _ => Err(SyntaxError::at(2, "expected a variable or an integer")),
+            },
+            _ => Err(SyntaxError::unrecognized()),
+        }
+    }
+}
+
+/// Handles `{when left op right: output}` using the default parser's helpers.
+struct WhenParser;
+
+impl Parser for WhenParser {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        match tokens {
+            [Token::Ident("when"), left, op, right, Token::Colon, output] => {
+                let op = comparison_op(op).ok_or(SyntaxError::at(2, "expected a comparison"))?;
+
+                Ok(Box::new(ConditionalDirective {
+                    cond: Argument::comparison(
+                        token_to_argument(left),
+                        op,
+                        token_to_argument(right),
+                    ),
+                    if_true: token_to_argument(output),
+                    if_false: Argument::literal(Cow::Borrowed("")),
+                }))
+            }
+            _ => Err(SyntaxError::unrecognized()),
+        }
+    }
+}
+
+/// Handles `{a | b}`, rendering `b` when `a` is empty.
+struct OrElseParser;
+
+impl Parser for OrElseParser {
+    fn parse(&self, tokens: &[Token]) -> Result<Box<dyn Directive + Send + Sync>, SyntaxError> {
+        match tokens {
+            [left @ Token::Ident(_), Token::Pipe, right @ Token::Ident(_)] => {
+                Ok(Box::new(ConditionalDirective {
+                    cond: Argument::comparison(
+                        token_to_argument(left),
+                        comparison_op(&Token::Equals).unwrap(),
+                        Argument::literal(Cow::Borrowed("")),
+                    ),
+                    if_true: token_to_argument(right),
+                    if_false: token_to_argument(left),
+                }))
+            }
+            _ => Err(SyntaxError::unrecognized()),
+        }
+    }
+}
+
+#[test]
+fn test_custom_parser_receives_pipe() {
+    let mut ctx = Context::new();
+    ctx.insert("nick", Value::static_str(""));
+    ctx.insert("name", Value::static_str("Ada"));
+    ctx.insert("price", Value::Float(2.5));
+
+    let template = CBTemplate::compile_with(&OrElseParser, "{nick | name}").unwrap();
+    assert_eq!(template.format(&ctx).unwrap(), "Ada");
+
+    let parser = OrElseParser.or(DefaultParser);
+    let template = CBTemplate::compile_with(&parser, "{nick | name} {price | >6.2}").unwrap();
+    assert_eq!(template.format(&ctx).unwrap(), "Ada   2.50");
+}
+
+#[test]
+fn test_chained_parser_falls_back_to_default() {
+    let parser = SumParser.or(DefaultParser);
+    let template = CBTemplate::compile_with(&parser, "{a + 2} {name} {'-':3}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(40));
+    ctx.insert("name", Value::static_str("sum"));
+
+    assert_eq!(template.format(&ctx).unwrap(), "42 sum ---");
+}
+
+#[test]
+fn test_chained_parser_reports_invalid_directive() {
+    let parser = SumParser.or(DefaultParser);
+    let result = CBTemplate::compile_with(&parser, "{a + 'x'}");
+
+    match result {
+        Err(TemplateError::DirectiveParsing { error, .. }) => {
+            assert!(!error.is_unrecognized());
+            assert_eq!(error.token, Some(2));
+        }
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_chain_of_custom_parsers() {
+    let parser = WhenParser.or(SumParser).or(DefaultParser);
+    let template =
+        CBTemplate::compile_with(&parser, "{when score >= 90: 'top'}|{score + 1}|{score}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("score", Value::Int(95));
+
+    assert_eq!(template.format(&ctx).unwrap(), "top|96|95");
+}
+
+#[test]
+fn test_chain_without_fallback_reports_unrecognized() {
+    let parser = WhenParser.or(SumParser);
+    let err = CBTemplate::compile_with(&parser, "{name}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { error, .. } => assert!(error.is_unrecognized()),
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+// ============================================
+// Function Call Tests
+// ============================================
+
+fn test_env() -> Environment {
+    let mut env = Environment::new();
+    env.register("max", |a: i64, b: i64| a.max(b));
+    env.register("upper", |s: Cow<'static, str>| s.to_uppercase());
+    env.register(
+        "format_money",
+        |amount: f64, currency: Cow<'static, str>| format!("{:.2} {}", amount, currency),
+    );
+    env.register("checked_div", |a: i64, b: i64| {
+        a.checked_div(b).ok_or(DirectiveError::ParseError {
+            value: b.to_string(),
+            type_name: "i64",
+            message: "division by zero".to_string(),
+        })
+    });
+    env.register_variadic("concat", |args| {
+        let mut out = String::new();
+        for arg in args {
+            if let Value::Str(s) = arg {
+                out.push_str(s);
+            }
+        }
+        Ok(Value::owned_str(out))
+    });
+    env
+}
+
+#[test]
+fn test_function_call_with_variables() {
+    let template = CBTemplate::compile("Max: {max(a, b)}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(3));
+    ctx.insert("b", Value::Int(7));
+
+    assert_eq!(template.format_with(&ctx, &test_env()).unwrap(), "Max: 7");
+}
+
+#[test]
+fn test_function_call_with_literals() {
+    let template = CBTemplate::compile("{format_money(amount, 'EUR')}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("amount", Value::Float(1234.5));
+
+    assert_eq!(
+        template.format_with(&ctx, &test_env()).unwrap(),
+        "1234.50 EUR"
+    );
+}
+
+#[test]
+fn test_nested_function_calls() {
+    let template =
+        CBTemplate::compile("{max(max(a, 10), b)} {upper(concat('a', 'b', name))}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(3));
+    ctx.insert("b", Value::Int(7));
+    ctx.insert("name", Value::static_str("c"));
+
+    assert_eq!(template.format_with(&ctx, &test_env()).unwrap(), "10 ABC");
+}
+
+#[test]
+fn test_function_call_in_conditional_and_repeat() {
+    let template =
+        CBTemplate::compile("{max(a, b) > 5 ? 'big' : 'small'} {'*':max(a, 2)}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(3));
+    ctx.insert("b", Value::Int(7));
+
+    assert_eq!(template.format_with(&ctx, &test_env()).unwrap(), "big ***");
+}
+
+#[test]
+fn test_function_call_without_arguments() {
+    let mut env = Environment::new();
+    env.register("answer", || 42);
+    let template = CBTemplate::compile("{answer()}").unwrap();
+
+    assert_eq!(template.format_with(&Context::new(), &env).unwrap(), "42");
+}
+
+#[test]
+fn test_unknown_function_error() {
+    let template = CBTemplate::compile("{missing(a)}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(1));
+
+    let err = template.format(&ctx).unwrap_err().into_root();
+    assert!(matches!(err, DirectiveError::UnknownFunction { ref name } if name == "missing"));
+}
+
+#[test]
+fn test_function_arity_error() {
+    let template = CBTemplate::compile("{max(a)}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(1));
+
+    let err = template
+        .format_with(&ctx, &test_env())
+        .unwrap_err()
+        .into_root();
+    assert!(matches!(
+        err,
+        DirectiveError::ArityMismatch {
+            expected: 2,
+            found: 1,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_function_argument_type_error() {
+    let template = CBTemplate::compile("{max(a, 'many')}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(1));
+
+    let err = template.format_with(&ctx, &test_env()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "{max(a, 'many')} at 1..15: Argument 2 of function 'max' has type 'string' but was expected to have type 'i64'"
+    );
+}
+
+#[test]
+fn test_function_returning_error() {
+    let template = CBTemplate::compile("{checked_div(a, 0)}").unwrap();
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(1));
+
+    let err = template
+        .format_with(&ctx, &test_env())
+        .unwrap_err()
+        .into_root();
+    assert!(matches!(err, DirectiveError::ParseError { .. }));
+}
+
+#[test]
+fn test_malformed_function_call_error() {
+    for input in ["{max(a, b}", "{max(a b)}", "{max(a,)}"] {
+        let result = CBTemplate::compile(input);
+        assert!(
+            matches!(result, Err(TemplateError::DirectiveParsing { .. })),
+            "{} should not compile",
+            input
+        );
+    }
+}
+
+// ============================================
+// Lexer Span Tests
+// ============================================
+
+#[test]
+fn test_spanned_tokens() {
+    let tokens: Vec<_> = TemplateLexer::new("count >= 10 ? 'many' : few")
+        .spanned()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert_eq!(
+        tokens,
+        [
+            Spanned {
+                token: Token::Ident("count"),
+                span: Span::new(0, 5)
+            },
+            Spanned {
+                token: Token::GreaterThanEquals,
+                span: Span::new(6, 8)
+            },
+            Spanned {
+                token: Token::Int("10"),
+                span: Span::new(9, 11)
+            },
+            Spanned {
+                token: Token::Question,
+                span: Span::new(12, 13)
+            },
+            Spanned {
+                token: Token::Literal(Cow::Borrowed("many")),
+                span: Span::new(14, 20)
+            },
+            Spanned {
+                token: Token::Colon,
+                span: Span::new(21, 22)
+            },
+            Spanned {
+                token: Token::Ident("few"),
+                span: Span::new(23, 26)
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_spanned_tokens_match_plain_iterator() {
+    let input = "max(a, 'x\\'y') != -2.5 && !flag";
+    let plain: Vec<_> = TemplateLexer::new(input).collect();
+    let spanned: Vec<_> = TemplateLexer::new(input)
+        .spanned()
+        .map(|t| t.unwrap().token)
+        .collect();
+
+    assert_eq!(plain, spanned);
+}
+
+#[test]
+fn test_spanned_unknown_character() {
+    let results: Vec<_> = TemplateLexer::new("a # é").spanned().collect();
+
+    assert_eq!(
+        results,
+        [
+            Ok(Spanned {
+                token: Token::Ident("a"),
+                span: Span::new(0, 1)
+            }),
+            Err(LexError::UnknownCharacter {
+                ch: '#',
+                span: Span::new(2, 3)
+            }),
+            Err(LexError::UnknownCharacter {
+                ch: 'é',
+                span: Span::new(4, 6)
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_spanned_unterminated_literal() {
+    let results: Vec<_> = TemplateLexer::new("x == 'abc").spanned().collect();
+
+    assert_eq!(
+        results.last(),
+        Some(&Err(LexError::UnterminatedLiteral {
+            span: Span::new(5, 9)
+        }))
+    );
+    assert_eq!(
+        results.last().unwrap().as_ref().unwrap_err().to_string(),
+        "Unterminated string literal at 5..9"
+    );
+}
+
+#[test]
+fn test_parse_error_span_points_into_template() {
+    let err = CBTemplate::compile("Hello {max(a b)}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { span, .. } => assert_eq!(span, Span::new(13, 14)),
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_error_span_at_end_of_directive() {
+    let err = CBTemplate::compile("{max(a, b}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { span, .. } => assert_eq!(span, Span::new(9, 9)),
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_lexer_unterminated_literal_token() {
+    let tokens: Vec<_> = TemplateLexer::new("'abc").collect();
+
+    assert_eq!(tokens, [Token::UnterminatedLiteral(Cow::Borrowed("abc"))]);
+}
+
+#[test]
+fn test_unterminated_literal_is_compile_error() {
+    let err = CBTemplate::compile("Say {'abc}!").unwrap_err();
+
+    match err {
+        TemplateError::UnterminatedLiteral { content, span } => {
+            assert_eq!(content, "'abc");
+            assert_eq!(span, Span::new(5, 9));
+        }
+        other => panic!("expected an unterminated literal error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unterminated_literal_in_conditional() {
+    let err = CBTemplate::compile("{ok ? \"yes : 'no'}").unwrap_err();
+
+    assert!(matches!(
+        err,
+        TemplateError::UnterminatedLiteral {
+            span: Span { start: 6, end: 17 },
+            ..
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Unterminated string literal in directive 'ok ? \"yes : 'no'' at 6..17"
+    );
+}
+
+// ============================================
+// Numeric Literal Tests
+// ============================================
+
+#[test]
+fn test_lexer_numeric_forms() {
+    let tokens: Vec<_> =
+        TemplateLexer::new("1_000 0xff 0XAB_CD 1e6 2.5E-3 7e+2 3.14 1_0.5").collect();
+
+    assert_eq!(
+        tokens,
+        [
+            Token::Int("1_000"),
+            Token::Int("0xff"),
+            Token::Int("0XAB_CD"),
+            Token::Float("1e6"),
+            Token::Float("2.5E-3"),
+            Token::Float("7e+2"),
+            Token::Float("3.14"),
+            Token::Float("1_0.5"),
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_exponent_requires_digits() {
+    let tokens: Vec<_> = TemplateLexer::new("1e 0x").collect();
+
+    assert_eq!(
+        tokens,
+        [
+            Token::Int("1"),
+            Token::Ident("e"),
+            Token::Int("0"),
+            Token::Ident("x"),
+        ]
+    );
+}
+
+#[test]
+fn test_negative_number_in_condition() {
+    let template = CBTemplate::compile("{x > -5 ? 'above' : 'below'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("x", Value::Int(-3));
+    assert_eq!(template.format(&ctx).unwrap(), "above");
+
+    ctx.insert("x", Value::Int(-7));
+    assert_eq!(template.format(&ctx).unwrap(), "below");
+}
+
+#[test]
+fn test_numeric_literal_forms_render() {
+    let template = CBTemplate::compile("{0xff} {1_000} {1e3} {-2.5e-1} {+4}").unwrap();
+
+    assert_eq!(
+        template.format(&Context::new()).unwrap(),
+        "255 1000 1000.0 -0.25 4"
+    );
+}
+
+#[test]
+fn test_hex_and_underscore_in_repeat_and_calls() {
+    let mut env = Environment::new();
+    env.register("add", |a: i64, b: i64| a + b);
+
+    let template = CBTemplate::compile("{'ab':0x3} {add(1_000, -0x10)}").unwrap();
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "ababab 984"
+    );
+}
+
+#[test]
+fn test_i64_min_literal() {
+    let template = CBTemplate::compile("{-9223372036854775808}").unwrap();
+
+    assert_eq!(
+        template.format(&Context::new()).unwrap(),
+        "-9223372036854775808"
+    );
+}
+
+#[test]
+fn test_out_of_range_number_is_error() {
+    let err = CBTemplate::compile("{x > 0x1_0000_0000_0000_0000 ? a : b}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { error, span, .. } => {
+            assert_eq!(error.token, Some(2));
+            assert_eq!(span, Span::new(5, 28));
+        }
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+
+    assert!(CBTemplate::compile("{1e999}").is_err());
+    assert!(CBTemplate::compile("{9223372036854775808}").is_err());
+}
+
+// ============================================
+// Typed Literal Tests
+// ============================================
+
+fn literal_types(source: &str) -> String {
+    let mut env = Environment::new();
+    env.register_variadic("types", |args| {
+        Ok(Value::owned_str(
+            args.iter()
+                .map(|arg| arg.type_name())
+                .collect::<Vec<_>>()
+                .join(","),
+        ))
+    });
+
+    CBTemplate::compile(source)
+        .unwrap()
+        .format_with(&Context::new(), &env)
+        .unwrap()
+}
+
+#[test]
+fn test_literals_keep_their_type() {
+    assert_eq!(
+        literal_types("{types('a', 1, -0x2, 1.5, 1e3, true, false)}"),
+        "string,integer,integer,float,float,boolean,boolean"
+    );
+}
+
+#[test]
+fn test_token_to_argument_types() {
+    assert!(matches!(
+        token_to_argument(&Token::Int("0x10")),
+        Argument::Literal(Value::Int(16))
+    ));
+    assert!(matches!(
+        token_to_argument(&Token::Float("2.5")),
+        Argument::Literal(Value::Float(f)) if f == 2.5
+    ));
+    assert!(matches!(
+        token_to_argument(&Token::Ident("true")),
+        Argument::Literal(Value::Bool(true))
+    ));
+    assert!(matches!(
+        token_to_argument(&Token::Literal(Cow::Borrowed("3"))),
+        Argument::Literal(Value::Str(_))
+    ));
+}
+
+#[test]
+fn test_boolean_literal_condition() {
+    let template = CBTemplate::compile("{true ? 'on' : 'off'} {!false ? 'on' : 'off'}").unwrap();
+
+    assert_eq!(template.format(&Context::new()).unwrap(), "on on");
+}
+
+#[test]
+fn test_float_literal_comparison() {
+    let template = CBTemplate::compile("{growth > 10.0 ? 'fast' : 'slow'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("growth", Value::Float(12.5));
+    assert_eq!(template.format(&ctx).unwrap(), "fast");
+
+    ctx.insert("growth", Value::Int(9));
+    assert_eq!(template.format(&ctx).unwrap(), "slow");
+}
+
+#[test]
+fn test_string_literal_still_converts_when_numeric() {
+    let template = CBTemplate::compile("{'-':'3'}").unwrap();
+
+    assert_eq!(template.format(&Context::new()).unwrap(), "---");
+}
+
+#[test]
+fn test_non_numeric_literal_count_fails_to_compile() {
+    let err = CBTemplate::compile("{'-':'many'}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { error, span, .. } => {
+            assert_eq!(error.token, Some(2));
+            assert_eq!(span, Span::new(5, 11));
+        }
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_argument_literal_from_values() {
+    let ctx = Context::new();
+    let env = Environment::new();
+    let scope = Scope::new(&ctx, &env);
+
+    assert_eq!(Argument::literal(7).resolve_as::<i64>(&scope).unwrap(), 7);
+    assert_eq!(
+        Argument::literal(2.5)
+            .resolve_as::<Cow<'static, str>>(&scope)
+            .unwrap(),
+        "2.5"
+    );
+    assert!(Argument::literal(true).resolve_as::<bool>(&scope).unwrap());
+}
+
+// ============================================
+// Comparison Semantics Tests
+// ============================================
+
+#[test]
+fn test_value_compare_rules() {
+    use std::cmp::Ordering;
+
+    assert_eq!(
+        Value::Int(10).compare(&Value::Float(10.0)),
+        Some(Ordering::Equal)
+    );
+    assert_eq!(
+        Value::Float(10.5).compare(&Value::Int(10)),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        Value::Int(-3).compare(&Value::Float(-2.5)),
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        Value::static_str("007").compare(&Value::static_str("6")),
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        Value::Bool(false).compare(&Value::Bool(true)),
+        Some(Ordering::Less)
+    );
+    assert_eq!(Value::Int(1).compare(&Value::static_str("1")), None);
+    assert_eq!(Value::Bool(true).compare(&Value::Int(1)), None);
+    assert_eq!(Value::Float(f64::NAN).compare(&Value::Float(1.0)), None);
+}
+
+#[test]
+fn test_large_integers_compare_exactly() {
+    use std::cmp::Ordering;
+
+    let big = Value::Int(9_007_199_254_740_993);
+    assert_eq!(
+        big.compare(&Value::Int(9_007_199_254_740_992)),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        big.compare(&Value::Float(9_007_199_254_740_992.0)),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        Value::Int(i64::MAX).compare(&Value::Float(9_223_372_036_854_775_808.0)),
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        Value::Int(i64::MIN).compare(&Value::Float(-9_223_372_036_854_775_808.0)),
+        Some(Ordering::Equal)
+    );
+}
+
+#[test]
+fn test_int_equals_float_in_template() {
+    let template = CBTemplate::compile("{a == b ? 'same' : 'different'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(10));
+    ctx.insert("b", Value::Float(10.0));
+
+    assert_eq!(template.format(&ctx).unwrap(), "same");
+}
+
+#[test]
+fn test_numeric_strings_compare_as_strings() {
+    let template = CBTemplate::compile("{a > b ? 'greater' : 'not greater'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::static_str("007"));
+    ctx.insert("b", Value::static_str("6"));
+
+    assert_eq!(template.format(&ctx).unwrap(), "not greater");
+}
+
+#[test]
+fn test_incomparable_types_error() {
+    let template = CBTemplate::compile("{count > 'many' ? a : b}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("count", Value::Int(3));
+
+    let err = template.format(&ctx).unwrap_err().into_root();
+    assert!(matches!(
+        &err,
+        DirectiveError::Incomparable { left, op: ">", right } if left == "integer" && right == "string"
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Cannot compare 'integer' with 'string' using '>'"
+    );
+}
+
+// ============================================
+// Null and Missing Variable Tests
+// ============================================
+
+fn missing_env(policy: MissingVariable) -> Environment {
+    let mut env = Environment::new();
+    env.set_missing_variable(policy);
+    env
+}
+
+#[test]
+fn test_null_value_renders_empty_and_is_falsy() {
+    let template = CBTemplate::compile("[{value}] {value ? 'set' : 'unset'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("value", Value::Null);
+
+    assert_eq!(template.format(&ctx).unwrap(), "[] unset");
+}
+
+#[test]
+fn test_null_literal_equality() {
+    let template = CBTemplate::compile("{a == null ? 'none' : 'some'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Null);
+    assert_eq!(template.format(&ctx).unwrap(), "none");
+
+    ctx.insert("a", Value::Int(1));
+    assert_eq!(template.format(&ctx).unwrap(), "some");
+}
+
+#[test]
+fn test_value_from_option() {
+    assert!(matches!(Value::from(None::<i64>), Value::Null));
+    assert!(matches!(Value::from(Some(3)), Value::Int(3)));
+}
+
+#[test]
+fn test_missing_variable_error_by_default() {
+    let template = CBTemplate::compile("Hello {name}!").unwrap();
+
+    assert!(matches!(
+        template
+            .format_with(&Context::new(), &Environment::new())
+            .map_err(DirectiveError::into_root),
+        Err(DirectiveError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_missing_variable_empty() {
+    let template = CBTemplate::compile("Hello {name}! {vip ? '*' : ''}").unwrap();
+    let env = missing_env(MissingVariable::Empty);
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "Hello ! "
+    );
+}
+
+#[test]
+fn test_missing_variable_original() {
+    let template = CBTemplate::compile("Hello {name}, you are {age >= 18 ? 'in' : 'out'}").unwrap();
+    let env = missing_env(MissingVariable::Original);
+
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Ann"));
+
+    assert_eq!(
+        template.format_with(&ctx, &env).unwrap(),
+        "Hello Ann, you are {age >= 18 ? 'in' : 'out'}"
+    );
+}
+
+#[test]
+fn test_missing_variable_original_repeat_block() {
+    let template =
+        CBTemplate::compile("[{repeat zz}x{{{y}{endrepeat}] [{repeat 2 sep s}-{endrepeat}]")
+            .unwrap();
+    let env = missing_env(MissingVariable::Original);
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "[{repeat zz}x{{{y}{endrepeat}] [{repeat 2 sep s}-{endrepeat}]"
+    );
+
+    // A variable missing in the body only affects its own directive
+    let template = CBTemplate::compile("{repeat 2}<{y}>{endrepeat}").unwrap();
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "<{y}><{y}>"
+    );
+}
+
+#[test]
+fn test_missing_variable_original_custom_delimiters() {
+    let template = ParenTemplate::compile("Hi (name) ((x))").unwrap();
+    let env = missing_env(MissingVariable::Original);
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "Hi (name) (x)"
+    );
+}
+
+#[test]
+fn test_missing_variable_placeholder() {
+    let template = CBTemplate::compile("{greeting}, {name}!").unwrap();
+    let env = missing_env(MissingVariable::Placeholder(Cow::Borrowed("???")));
+
+    let mut ctx = Context::new();
+    ctx.insert("greeting", Value::static_str("Hi"));
+
+    assert_eq!(template.format_with(&ctx, &env).unwrap(), "Hi, ???!");
+}
+
+#[test]
+fn test_missing_variable_policy_keeps_other_errors() {
+    let template = CBTemplate::compile("{a > 'x' ? b : c}").unwrap();
+    let env = missing_env(MissingVariable::Empty);
+
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(1));
+
+    assert!(matches!(
+        template
+            .format_with(&ctx, &env)
+            .map_err(DirectiveError::into_root),
+        Err(DirectiveError::Incomparable { .. })
+    ));
+}
+
+// ============================================
+// Null Coalescing Tests
+// ============================================
+
+#[test]
+fn test_lexer_coalesce_token() {
+    let tokens: Vec<_> = TemplateLexer::new("a ?? b ? c : d").collect();
+
+    assert_eq!(
+        tokens,
+        [
+            Token::Ident("a"),
+            Token::Coalesce,
+            Token::Ident("b"),
+            Token::Question,
+            Token::Ident("c"),
+            Token::Colon,
+            Token::Ident("d"),
+        ]
+    );
+}
+
+#[test]
+fn test_coalesce_falls_through_missing_and_null() {
+    let template = CBTemplate::compile("{nickname ?? name ?? 'anonymous'}").unwrap();
+
+    let mut ctx = Context::new();
+    assert_eq!(template.format(&ctx).unwrap(), "anonymous");
+
+    ctx.insert("name", Value::static_str("Alice"));
+    assert_eq!(template.format(&ctx).unwrap(), "Alice");
+
+    ctx.insert("nickname", Value::Null);
+    assert_eq!(template.format(&ctx).unwrap(), "Alice");
+
+    ctx.insert("nickname", Value::static_str("Ali"));
+    assert_eq!(template.format(&ctx).unwrap(), "Ali");
+}
+
+#[test]
+fn test_coalesce_keeps_falsy_values() {
+    let template = CBTemplate::compile("{count ?? 10}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("count", Value::Int(0));
+
+    assert_eq!(template.format(&ctx).unwrap(), "0");
+}
+
+#[test]
+fn test_coalesce_last_operand_missing_is_not_found() {
+    let template = CBTemplate::compile("{a ?? b}").unwrap();
+
+    assert!(matches!(
+        template.format(&Context::new()).map_err(DirectiveError::into_root),
+        Err(DirectiveError::NotFound { name, .. }) if name == "b"
+    ));
+}
+
+#[test]
+fn test_coalesce_in_condition_and_call() {
+    let mut env = Environment::new();
+    env.register("double", |x: i64| x * 2);
+
+    let template =
+        CBTemplate::compile("{limit ?? 5 > 3 ? 'high' : 'low'} {double(n ?? 21)}").unwrap();
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "high 42"
+    );
+}
+
+#[test]
+fn test_coalesce_resolve_as() {
+    let ctx = Context::new();
+    let env = Environment::new();
+    let scope = Scope::new(&ctx, &env);
+
+    let arg = Argument::coalesce(vec![
+        Argument::variable(Cow::Borrowed("missing")),
+        Argument::literal(7),
+    ]);
+
+    assert_eq!(arg.resolve_as::<i64>(&scope).unwrap(), 7);
+}
+
+#[test]
+fn test_coalesce_literal_fallback_is_not_escaped() {
+    let mut ctx = Context::new();
+    ctx.insert("nul", Value::Null);
+    ctx.insert("tag", Value::static_str("<i>"));
+
+    assert_eq!(
+        escaped(Escape::Html, "{nul ?? '<b>'}|{missing ?? '<b>'}", &ctx),
+        "<b>|<b>"
+    );
+    assert_eq!(
+        escaped(Escape::Html, "{tag ?? '<b>'}|{nul ?? tag}", &ctx),
+        "&lt;i&gt;|&lt;i&gt;"
+    );
+    assert_eq!(
+        escaped(Escape::Html, "{false ? tag : '<b>'}", &ctx),
+        escaped(Escape::Html, "{nul ?? '<b>'}", &ctx)
+    );
+}
+
+#[test]
+fn test_coalesce_requires_operand() {
+    let err = CBTemplate::compile("{name ??}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { error, span, .. } => {
+            assert_eq!(error.token, Some(2));
+            assert_eq!(span, Span::new(8, 8));
+        }
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+// ============================================
+// Format Spec Tests
+// ============================================
+
+fn render(source: &str, ctx: &Context) -> String {
+    CBTemplate::compile(source).unwrap().format(ctx).unwrap()
+}
+
+#[test]
+fn test_format_spec_parse() {
+    let spec: FormatSpec = "*^+010,.3%".parse().unwrap();
+
+    assert_eq!(
+        spec,
+        FormatSpec {
+            fill: '*',
+            align: Some(Align::Center),
+            sign: true,
+            zero: true,
+            width: Some(Count::Fixed(10)),
+            grouping: true,
+            precision: Some(Count::Fixed(3)),
+            style: NumberStyle::Percent,
+        }
+    );
+
+    let spec: FormatSpec = "<w.p".parse().unwrap();
+    assert_eq!(spec.width, Some(Count::Variable(Cow::Borrowed("w"))));
+    assert_eq!(spec.precision, Some(Count::Variable(Cow::Borrowed("p"))));
+
+    assert!("10.".parse::<FormatSpec>().is_err());
+    assert!("10x!".parse::<FormatSpec>().is_err());
+}
+
+#[test]
+fn test_format_alignment_and_fill() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Bob"));
+
+    assert_eq!(
+        render("[{name | 6}][{name | >6}][{name | *^7}]", &ctx),
+        "[Bob   ][   Bob][**Bob**]"
+    );
+}
+
+#[test]
+fn test_format_numbers() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(42));
+    ctx.insert("neg", Value::Int(-42));
+    ctx.insert("pi", Value::Float(3.14159));
+
+    assert_eq!(
+        render(
+            "[{n | 5}][{neg | 06}][{n | +}][{pi | .2}][{pi | +08.3}]",
+            &ctx
+        ),
+        "[   42][-00042][+42][3.14][+003.142]"
+    );
+}
+
+#[test]
+fn test_format_precision_truncates_strings() {
+    let mut ctx = Context::new();
+    ctx.insert("word", Value::static_str("héllo world"));
+
+    assert_eq!(render("{word | .5}", &ctx), "héllo");
+}
+
+#[test]
+fn test_format_width_from_variable() {
+    let mut ctx = Context::new();
+    ctx.insert("w", Value::Int(5));
+    ctx.insert("p", Value::Int(1));
+    ctx.insert("x", Value::Float(2.25));
+
+    assert_eq!(render("[{x | >w.p}]", &ctx), "[  2.2]");
+
+    ctx.insert("w", Value::Int(-1));
+    let template = CBTemplate::compile("{x | w}").unwrap();
+    assert!(matches!(
+        template.format(&ctx).map_err(DirectiveError::into_root),
+        Err(DirectiveError::TypeError { .. })
+    ));
+}
+
+#[test]
+fn test_format_applies_to_any_substitution() {
+    let mut env = Environment::new();
+    env.register("half", |x: f64| x / 2.0);
+
+    let mut ctx = Context::new();
+    ctx.insert("ok", Value::Bool(true));
+    ctx.insert("n", Value::Int(5));
+
+    let template = CBTemplate::compile(
+        "[{ok ? 'yes' : 'no' | >4}][{half(n) | .3}][{nick ?? 'anon' | -<6}][{'lit' | ^5}]",
+    )
+    .unwrap();
+
+    assert_eq!(
+        template.format_with(&ctx, &env).unwrap(),
+        "[ yes][2.500][anon--][ lit ]"
+    );
+}
+
+#[test]
+fn test_format_with_custom_parser() {
+    let template =
+        CBTemplate::compile_with(&ShoutParser { quiet: false }, "[{name | >6}]").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("bob"));
+
+    assert_eq!(template.format(&ctx).unwrap(), "[   BOB]");
+}
+
+#[test]
+fn test_format_pipe_inside_literal_and_or() {
+    let ctx = Context::new();
+
+    assert_eq!(render("{'a|b' | >5}", &ctx), "  a|b");
+    assert_eq!(render("{'x' | |^5}", &ctx), "||x||");
+}
+
+#[test]
+fn test_invalid_format_spec_error_span() {
+    let err = CBTemplate::compile("Total: {price | >10.}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { span, .. } => assert_eq!(span, Span::new(16, 20)),
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_format_spec_kept_in_original_source() {
+    let template = CBTemplate::compile("{price | >8.2}").unwrap();
+    let env = missing_env(MissingVariable::Original);
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "{price | >8.2}"
+    );
+}
+
+// ============================================
+// Locale Number Formatting Tests
+// ============================================
+
+fn render_in(locale: Locale, source: &str, ctx: &Context) -> String {
+    let mut env = Environment::new();
+    env.set_locale(locale);
+
+    CBTemplate::compile(source)
+        .unwrap()
+        .format_with(ctx, &env)
+        .unwrap()
+}
+
+fn money_ctx() -> Context {
+    let mut ctx = Context::new();
+    ctx.insert("revenue", Value::Int(1_250_000));
+    ctx.insert("price", Value::Float(1234.5));
+    ctx.insert("loss", Value::Float(-9876.543));
+    ctx.insert("growth", Value::Float(0.125));
+    ctx
+}
+
+#[test]
+fn test_grouping_en_us() {
+    assert_eq!(
+        render_in(
+            Locale::EN_US,
+            "{revenue | ,} {revenue | ,.2$} {price | ,.2}",
+            &money_ctx()
+        ),
+        "1,250,000 $1,250,000.00 1,234.50"
+    );
+}
+
+#[test]
+fn test_grouping_de_de() {
+    assert_eq!(
+        render_in(
+            Locale::DE_DE,
+            "{revenue | ,.2$} {price | ,.2}",
+            &money_ctx()
+        ),
+        "1.250.000,00\u{a0}€ 1.234,50"
+    );
+}
+
+#[test]
+fn test_integer_precision() {
+    let mut ctx = money_ctx();
+    ctx.insert("qty", Value::Int(-3));
+
+    assert_eq!(
+        render_in(Locale::EN_US, "{revenue | ,.2} {qty | .1}", &ctx),
+        "1,250,000.00 -3.0"
+    );
+    assert_eq!(
+        render_in(Locale::DE_DE, "{revenue | ,.2} {qty | .1}", &ctx),
+        "1.250.000,00 -3,0"
+    );
+    assert_eq!(render_in(Locale::EN_US, "{revenue | .0}", &ctx), "1250000");
+}
+
+#[test]
+fn test_locale_decimal_separator_without_grouping() {
+    assert_eq!(
+        render_in(Locale::DE_DE, "{price | .1} {revenue}", &money_ctx()),
+        "1234,5 1250000"
+    );
+}
+
+#[test]
+fn test_currency_sign_and_default_decimals() {
+    assert_eq!(
+        render_in(Locale::EN_US, "{loss | ,$} {revenue | $}", &money_ctx()),
+        "-$9,876.54 $1250000.00"
+    );
+    assert_eq!(
+        render_in(Locale::FR_FR, "{loss | ,$}", &money_ctx()),
+        "-9\u{202f}876,54\u{a0}€"
+    );
+}
+
+#[test]
+fn test_percent() {
+    let mut ctx = money_ctx();
+    ctx.insert("ratio", Value::Int(3));
+
+    assert_eq!(
+        render_in(Locale::EN_US, "{growth | .1%} {ratio | %}", &ctx),
+        "12.5% 300%"
+    );
+    assert_eq!(
+        render_in(Locale::DE_DE, "{growth | .1%}", &ctx),
+        "12,5\u{a0}%"
+    );
+}
+
+#[test]
+fn test_localized_numbers_pad_to_width() {
+    assert_eq!(
+        render_in(
+            Locale::EN_US,
+            "[{revenue | >15,.2$}][{price | 010,.1}]",
+            &money_ctx()
+        ),
+        "[  $1,250,000.00][0001,234.5]"
+    );
+}
+
+#[test]
+fn test_locale_from_tag() {
+    assert_eq!(Locale::from_tag("de_DE"), Some(Locale::DE_DE));
+    assert_eq!(Locale::from_tag("EN-us"), Some(Locale::EN_US));
+    assert_eq!(Locale::from_tag("xx-YY"), None);
+}
+
+// ============================================
+// Pluralization Tests
+// ============================================
+
+fn plural_with(rule: PluralRule, source: &str, n: i64) -> String {
+    let mut env = Environment::new();
+    env.set_locale(Locale {
+        plural: rule,
+        ..Locale::EN_US
+    });
+
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(n));
+
+    CBTemplate::compile(source)
+        .unwrap()
+        .format_with(&ctx, &env)
+        .unwrap()
+}
+
+#[test]
+fn test_plural_english() {
+    let template =
+        CBTemplate::compile("{plural n =0: 'no items', one: '# item', other: '# items'}").unwrap();
+
+    let mut ctx = Context::new();
+    for (n, expected) in [(0, "no items"), (1, "1 item"), (3, "3 items")] {
+        ctx.insert("n", Value::Int(n));
+        assert_eq!(template.format(&ctx).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_plural_russian_categories() {
+    let source = "{plural n one: '# файл' few: '# файла' many: '# файлов' other: '# файла'}";
+
+    assert_eq!(plural_with(PluralRule::EastSlavic, source, 1), "1 файл");
+    assert_eq!(plural_with(PluralRule::EastSlavic, source, 3), "3 файла");
+    assert_eq!(plural_with(PluralRule::EastSlavic, source, 11), "11 файлов");
+    assert_eq!(plural_with(PluralRule::EastSlavic, source, 21), "21 файл");
+    assert_eq!(plural_with(PluralRule::EastSlavic, source, 25), "25 файлов");
+}
+
+#[test]
+fn test_plural_falls_back_to_other() {
+    assert_eq!(
+        plural_with(
+            PluralRule::Arabic,
+            "{plural n one: 'one' other: 'other'}",
+            2
+        ),
+        "other"
+    );
+}
+
+#[test]
+fn test_plural_locale_french() {
+    let mut env = Environment::new();
+    env.set_locale(Locale::FR_FR);
+
+    let template = CBTemplate::compile("{plural n one: '# jour' other: '# jours'}").unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(0));
+
+    assert_eq!(template.format_with(&ctx, &env).unwrap(), "0 jour");
+}
+
+#[test]
+fn test_plural_rule_categories() {
+    use PluralCategory::*;
+
+    let cases = [
+        (PluralRule::Invariant, [Other, Other, Other, Other, Other]),
+        (PluralRule::OneOther, [Other, One, Other, Other, Other]),
+        (PluralRule::French, [One, One, Other, Other, Many]),
+        (PluralRule::Polish, [Many, One, Few, Many, Many]),
+        (PluralRule::Czech, [Other, One, Few, Other, Other]),
+        (PluralRule::Arabic, [Zero, One, Two, Many, Other]),
+    ];
+
+    for (rule, expected) in cases {
+        let actual = [0, 1, 2, 12, 1_000_000].map(|n| rule.category(n));
+        assert_eq!(actual, expected, "{:?}", rule);
+    }
+
+    assert_eq!(PluralRule::Polish.category(22), Few);
+    assert_eq!(PluralRule::Arabic.category(105), Few);
+}
+
+#[test]
+fn test_plural_rule_for_language() {
+    assert_eq!(
+        PluralRule::for_language("ru-RU"),
+        Some(PluralRule::EastSlavic)
+    );
+    assert_eq!(PluralRule::for_language("pt-BR"), Some(PluralRule::French));
+    assert_eq!(PluralRule::for_language("pt_BR"), Some(PluralRule::French));
+    assert_eq!(PluralRule::for_language("tr"), Some(PluralRule::OneOther));
+    assert_eq!(PluralRule::for_language("ja"), Some(PluralRule::Invariant));
+    assert_eq!(PluralRule::for_language("xx"), None);
+
+    let portuguese = PluralRule::for_language("pt-BR").unwrap();
+    assert_eq!(portuguese.category(0), PluralCategory::One);
+    assert_eq!(portuguese.category(1), PluralCategory::One);
+    assert_eq!(portuguese.category(2), PluralCategory::Other);
+
+    assert_eq!(PluralRule::for_language("pt"), Some(PluralRule::French));
+    assert_eq!(
+        PluralRule::for_language("pt-PT"),
+        Some(PluralRule::OneOther)
+    );
+    assert_eq!(
+        PluralRule::for_language("pt_pt"),
+        Some(PluralRule::OneOther)
+    );
+
+    let european = PluralRule::for_language("pt-PT").unwrap();
+    assert_eq!(european.category(0), PluralCategory::Other);
+    assert_eq!(european.category(1), PluralCategory::One);
+}
+
+#[test]
+fn test_plural_keyword_stays_a_variable() {
+    let mut ctx = Context::new();
+    ctx.insert("plural", Value::static_str("many"));
+
+    assert_eq!(
+        render("{plural} {plural == 'many' ? 'y' : 'n'}", &ctx),
+        "many y"
+    );
+}
+
+#[test]
+fn test_plural_requires_other() {
+    let err = CBTemplate::compile("{plural n one: 'item'}").unwrap_err();
+
+    assert!(matches!(
+        err,
+        TemplateError::DirectiveParsing { error, .. } if error.message == "plural forms must include 'other'"
+    ));
+}
+
+#[test]
+fn test_plural_unknown_category() {
+    let err = CBTemplate::compile("{plural n single: 'item' other: 'items'}").unwrap_err();
+
+    match err {
+        TemplateError::DirectiveParsing { error, span, .. } => {
+            assert_eq!(error.token, Some(2));
+            assert_eq!(span, Span::new(10, 16));
+        }
+        other => panic!("expected a parsing error, got {:?}", other),
+    }
+}
+
+// ============================================
+// Escaping Tests
+// ============================================
+
+fn escaped(escape: Escape, source: &str, ctx: &Context) -> String {
+    let mut env = Environment::new();
+    env.set_escape(escape);
+
+    CBTemplate::compile(source)
+        .unwrap()
+        .format_with(ctx, &env)
+        .unwrap()
+}
+
+#[test]
+fn test_escape_modes() {
+    let text = "a<b & \"c\", 'd'\n";
+
+    assert_eq!(Escape::None.escape(text), text);
+    assert_eq!(
+        Escape::Html.escape(text),
+        "a&lt;b &amp; &quot;c&quot;, &#39;d&#39;\n"
+    );
+    assert_eq!(
+        Escape::XmlAttribute.escape(text),
+        "a&lt;b &amp; &quot;c&quot;, &apos;d&apos;&#10;"
+    );
+    assert_eq!(Escape::Json.escape(text), "a<b & \\\"c\\\", 'd'\\n");
+    assert_eq!(Escape::Shell.escape(text), "'a<b & \"c\", '\\''d'\\''\n'");
+    assert_eq!(Escape::Csv.escape(text), "\"a<b & \"\"c\"\", 'd'\n\"");
+}
+
+#[test]
+fn test_escape_borrows_clean_text() {
+    for escape in [
+        Escape::Html,
+        Escape::XmlAttribute,
+        Escape::Json,
+        Escape::Csv,
+    ] {
+        assert!(matches!(escape.escape("plain text"), Cow::Borrowed(_)));
+    }
+
+    assert_eq!(Escape::Shell.escape(""), "''");
+    assert_eq!(Escape::Json.escape("\u{1}\\"), "\\u0001\\\\");
+}
+
+#[test]
+fn test_escape_html_substitutions_only() {
+    let mut ctx = Context::new();
+    ctx.insert("comment", Value::static_str("<script>alert(1)</script>"));
+
+    assert_eq!(
+        escaped(Escape::Html, "<p class=\"c\">{comment}</p>", &ctx),
+        "<p class=\"c\">&lt;script&gt;alert(1)&lt;/script&gt;</p>"
+    );
+}
+
+#[test]
+fn test_escape_keeps_string_literals() {
+    let mut ctx = Context::new();
+    ctx.insert("bold", Value::Bool(true));
+    ctx.insert("name", Value::static_str("<Tom>"));
+
+    assert_eq!(
+        escaped(Escape::Html, "{bold ? '<b>' : ''}{name}{'<br>':2}", &ctx),
+        "<b>&lt;Tom&gt;<br><br>"
+    );
+}
+
+#[test]
+fn test_escape_safe_values() {
+    let mut ctx = Context::new();
+    ctx.insert("body", Value::safe("<em>trusted</em>"));
+    ctx.insert("title", Value::static_str("<em>user</em>"));
+
+    assert_eq!(
+        escaped(Escape::Html, "{body} {title} {body | >20}", &ctx),
+        "<em>trusted</em> &lt;em&gt;user&lt;/em&gt;     <em>trusted</em>"
+    );
+}
+
+#[test]
+fn test_escape_function_results() {
+    let mut env = Environment::new();
+    env.set_escape(Escape::Html);
+    env.register("tag", |name: Cow<'static, str>| format!("<{}>", name));
+    env.register_variadic("trusted", |_| Ok(Value::safe("<hr>")));
+
+    let template = CBTemplate::compile("{tag('p')}{trusted()}").unwrap();
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "&lt;p&gt;<hr>"
+    );
+}
+
+#[test]
+fn test_escape_json_and_csv() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Say \"hi\""));
+    ctx.insert("count", Value::Int(3));
+
+    assert_eq!(
+        escaped(
+            Escape::Json,
+            "{{\"name\": \"{name}\", \"count\": {count}}}",
+            &ctx
+        ),
+        "{\"name\": \"Say \\\"hi\\\"\", \"count\": 3}"
+    );
+    assert_eq!(
+        escaped(Escape::Csv, "{name},{count}", &ctx),
+        "\"Say \"\"hi\"\"\",3"
+    );
+}
+
+#[test]
+fn test_escape_template_overrides_environment() {
+    let mut ctx = Context::new();
+    ctx.insert("file", Value::static_str("my file.txt"));
+
+    let mut template = CBTemplate::compile("rm {file}").unwrap();
+    assert_eq!(template.escape(), None);
+
+    template.set_escape(Escape::Shell);
+
+    let mut env = Environment::new();
+    env.set_escape(Escape::Html);
+
+    assert_eq!(
+        template.format_with(&ctx, &env).unwrap(),
+        "rm 'my file.txt'"
+    );
+    assert_eq!(template.format(&ctx).unwrap(), "rm 'my file.txt'");
+}
+
+#[test]
+fn test_escape_missing_variable_placeholder() {
+    let mut env = missing_env(MissingVariable::Placeholder("<missing>".into()));
+    env.set_escape(Escape::Html);
+
+    let template = CBTemplate::compile("<i>{name}</i>").unwrap();
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "<i><missing></i>"
+    );
+}
+
+#[test]
+fn test_safe_value_behaves_like_string() {
+    let safe = Value::safe("abc");
+
+    assert_eq!(safe.type_name(), "string");
+    assert_eq!(
+        safe.compare(&Value::static_str("abc")),
+        Some(std::cmp::Ordering::Equal)
+    );
+
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::safe("42"));
+    assert_eq!(
+        render("{n == '42' ? 'same' : 'different'} {n}", &ctx),
+        "same 42"
+    );
+}
+
+// ============================================
+// Loader Tests
+// ============================================
+
+type CBLoader = Loader<'{', '}'>;
+
+fn memory_loader(templates: &[(&'static str, &'static str)]) -> CBLoader {
+    let mut source = MemorySource::new();
+
+    for (name, template) in templates {
+        source.insert(*name, *template);
+    }
+
+    CBLoader::new(source)
+}
+
+#[test]
+fn test_loader_include_passes_context() {
+    let loader = memory_loader(&[
+        ("header", "[{title}]"),
+        ("footer", "-- {author}"),
+        ("page", "{> header}\n{body}\n{> 'footer'}"),
+    ]);
+
+    let mut ctx = Context::new();
+    ctx.insert("title", Value::static_str("News"));
+    ctx.insert("body", Value::static_str("Nothing happened."));
+    ctx.insert("author", Value::static_str("Alice"));
+
+    assert_eq!(
+        loader.render("page", &ctx, &Environment::new()).unwrap(),
+        "[News]\nNothing happened.\n-- Alice"
+    );
+}
+
+#[test]
+fn test_loader_nested_includes() {
+    let loader = memory_loader(&[("a", "a({> b})"), ("b", "b({> c})"), ("c", "c")]);
+
+    assert_eq!(
+        loader
+            .render("a", &Context::new(), &Environment::new())
+            .unwrap(),
+        "a(b(c))"
+    );
+}
+
+#[test]
+fn test_loader_compiles_once() {
+    let loader = memory_loader(&[("page", "{> part}{> part}"), ("part", "x")]);
+
+    let first = loader.get("page").unwrap();
+    let second = loader.get("page").unwrap();
+
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+    assert_eq!(first.includes().collect::<Vec<_>>(), ["part", "part"]);
+}
+
+#[test]
+fn test_loader_detects_cycles() {
+    let loader = memory_loader(&[("a", "{> b}"), ("b", "{> c}"), ("c", "{> a}")]);
+
+    match loader.get("a").unwrap_err() {
+        LoadError::Cycle { chain } => assert_eq!(chain, ["a", "b", "c", "a"]),
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+
+    let loader = memory_loader(&[("self", "{> self}")]);
+    assert!(matches!(
+        loader.get("self"),
+        Err(LoadError::Cycle { chain }) if chain == ["self", "self"]
+    ));
+}
+
+#[test]
+fn test_loader_missing_include() {
+    let loader = memory_loader(&[("page", "{> nowhere}")]);
+
+    assert!(matches!(
+        loader.get("page"),
+        Err(LoadError::NotFound { name }) if name == "nowhere"
+    ));
+    assert!(matches!(
+        loader.get("other"),
+        Err(LoadError::NotFound { name }) if name == "other"
+    ));
+}
+
+#[test]
+fn test_loader_compile_and_render_errors() {
+    let loader = memory_loader(&[("broken", "{name"), ("page", "{missing}")]);
+
+    assert!(matches!(
+        loader.get("broken"),
+        Err(LoadError::Compile { name, source: TemplateError::MissingDelimiter('}') }) if name == "broken"
+    ));
+    assert!(matches!(
+        loader.render("page", &Context::new(), &Environment::new()),
+        Err(LoadError::Render { name, source })
+            if name == "page" && matches!(source.root(), DirectiveError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_loader_closure_source() {
+    let loader = CBLoader::new(|name: &str| {
+        Ok(Some(match name {
+            "outer" => "<{> inner}>".to_string(),
+            other => other.to_uppercase(),
+        }))
+    });
+
+    assert_eq!(
+        loader
+            .render("outer", &Context::new(), &Environment::new())
+            .unwrap(),
+        "<INNER>"
+    );
+}
+
+#[test]
+fn test_loader_directory_source() {
+    let root = std::env::temp_dir().join(format!("figura-loader-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("partials")).unwrap();
+    std::fs::write(root.join("page.txt"), "{> partials/header}{body}").unwrap();
+    std::fs::write(root.join("partials/header.txt"), "# {title}\n").unwrap();
+
+    let loader = CBLoader::new(DirectorySource::new(&root).with_extension("txt"));
+
+    let mut ctx = Context::new();
+    ctx.insert("title", Value::static_str("Title"));
+    ctx.insert("body", Value::static_str("Body"));
+
+    let output = loader.render("page", &ctx, &Environment::new());
+    let missing = loader.get("absent");
+    let outside = loader.get("../page");
+
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(output.unwrap(), "# Title\nBody");
+    assert!(matches!(missing, Err(LoadError::NotFound { .. })));
+    assert!(
+        matches!(outside, Err(LoadError::Io { source, .. }) if source.kind() == std::io::ErrorKind::InvalidInput)
+    );
+}
+
+#[test]
+fn test_include_without_loader() {
+    let template = CBTemplate::compile("{> header}").unwrap();
+
+    assert!(matches!(
+        template.format(&Context::new()).map_err(DirectiveError::into_root),
+        Err(DirectiveError::Include { name, .. }) if name == "header"
+    ));
+}
+
+#[test]
+fn test_include_requires_name() {
+    for source in ["{>}", "{> two words}", "{> ''}"] {
+        assert!(
+            matches!(
+                CBTemplate::compile(source),
+                Err(TemplateError::DirectiveParsing { .. })
+            ),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_include_output_is_not_escaped_twice() {
+    let loader = memory_loader(&[("link", "<a>{label}</a>"), ("page", "<p>{> link}</p>")]);
+
+    let mut env = Environment::new();
+    env.set_escape(Escape::Html);
+
+    let mut ctx = Context::new();
+    ctx.insert("label", Value::static_str("Tom & Jerry"));
+
+    assert_eq!(
+        loader.render("page", &ctx, &env).unwrap(),
+        "<p><a>Tom &amp; Jerry</a></p>"
+    );
+}
+
+// ============================================
+// Inheritance Tests
+// ============================================
+
+fn email_loader(extra: &[(&'static str, &'static str)]) -> CBLoader {
+    let mut templates = vec![(
+        "base",
+        "{block header}Hello {name},{endblock}\n{block body}(no content){endblock}\n{block footer}-- {sender}{endblock}",
+    )];
+    templates.extend_from_slice(extra);
+
+    memory_loader(&templates)
+}
+
+fn email_ctx() -> Context {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Bob"));
+    ctx.insert("sender", Value::static_str("Acme"));
+    ctx.insert("code", Value::Int(1234));
+    ctx
+}
+
+#[test]
+fn test_extends_overrides_blocks() {
+    let loader = email_loader(&[(
+        "reset",
+        "{extends base}\nignored text\n{block body}Your code is {code}.{endblock}",
+    )]);
+
+    assert_eq!(
+        loader
+            .render("reset", &email_ctx(), &Environment::new())
+            .unwrap(),
+        "Hello Bob,\nYour code is 1234.\n-- Acme"
+    );
+}
+
+#[test]
+fn test_parent_renders_its_own_blocks() {
+    let loader = email_loader(&[]);
+
+    assert_eq!(
+        loader
+            .render("base", &email_ctx(), &Environment::new())
+            .unwrap(),
+        "Hello Bob,\n(no content)\n-- Acme"
+    );
+}
+
+#[test]
+fn test_extends_multiple_levels() {
+    let loader = email_loader(&[
+        (
+            "notice",
+            "{extends 'base'}{block body}[{block message}notice{endblock}]{endblock}",
+        ),
+        (
+            "outage",
+            "{extends notice}{block message}service down{endblock}{block footer}-- Ops{endblock}",
+        ),
+    ]);
+
+    assert_eq!(
+        loader
+            .render("outage", &email_ctx(), &Environment::new())
+            .unwrap(),
+        "Hello Bob,\n[service down]\n-- Ops"
+    );
+
+    let merged = loader.get("outage").unwrap();
+    assert_eq!(merged.extends(), None);
+    assert_eq!(
+        merged.blocks().collect::<Vec<_>>(),
+        ["header", "body", "message", "footer"]
+    );
+}
+
+#[test]
+fn test_extends_overrides_nested_block() {
+    let loader = memory_loader(&[
+        (
+            "layout",
+            "<{block page}<{block title}Untitled{endblock}>{endblock}>",
+        ),
+        ("home", "{extends layout}{block title}Home{endblock}"),
+    ]);
+
+    assert_eq!(
+        loader
+            .render("home", &Context::new(), &Environment::new())
+            .unwrap(),
+        "<<Home>>"
+    );
+}
+
+#[test]
+fn test_extends_keeps_directive_sources() {
+    let loader = email_loader(&[("plain", "{extends base}{block body}{missing}{endblock}")]);
+
+    let mut env = Environment::new();
+    env.set_missing_variable(MissingVariable::Original);
+
+    assert_eq!(
+        loader.render("plain", &email_ctx(), &env).unwrap(),
+        "Hello Bob,\n{missing}\n-- Acme"
+    );
+}
+
+#[test]
+fn test_extends_cycle() {
+    let loader = memory_loader(&[("a", "{extends b}"), ("b", "{extends a}")]);
+
+    assert!(matches!(
+        loader.get("a"),
+        Err(LoadError::Cycle { chain }) if chain == ["a", "b", "a"]
+    ));
+}
+
+#[test]
+fn test_extends_requires_loader() {
+    let template = CBTemplate::compile("{extends base}{block body}x{endblock}").unwrap();
+
+    assert_eq!(template.extends(), Some("base"));
+    assert!(matches!(
+        template.format(&Context::new()).map_err(DirectiveError::into_root),
+        Err(DirectiveError::UnresolvedExtends { name }) if name == "base"
+    ));
+}
+
+#[test]
+fn test_block_syntax_errors() {
+    assert!(matches!(
+        CBTemplate::compile("{block body}text"),
+        Err(TemplateError::UnclosedBlock { name }) if name == "body"
+    ));
+
+    for source in [
+        "{endblock}",
+        "{block a}{endblock}{block a}{endblock}",
+        "{block a}{endblock b}",
+        "{extends a}{extends b}",
+        "{block a}{macro m()}x{endblock}{endmacro}",
+    ] {
+        assert!(
+            matches!(
+                CBTemplate::compile(source),
+                Err(TemplateError::DirectiveParsing { .. })
+            ),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_extends_base_closing_block_inside_macro() {
+    let loader = memory_loader(&[
+        ("base", "{block a}{macro m()}x{endblock}{endmacro}after"),
+        ("child", "{extends base}{block a}y{endblock}"),
+    ]);
+
+    assert!(matches!(
+        loader.render("child", &Context::new(), &Environment::new()),
+        Err(LoadError::Compile { name, .. }) if name == "base"
+    ));
+}
+
+#[test]
+fn test_block_keywords_stay_variables() {
+    let mut ctx = Context::new();
+    ctx.insert("block", Value::static_str("b"));
+    ctx.insert("extends", Value::static_str("e"));
+
+    assert_eq!(
+        render("{block}{extends}{block a}!{endblock a}", &ctx),
+        "be!"
+    );
+    assert_eq!(
+        render(
+            "{block == 'b' ? 1 : 2} {extends != 'e' ? 1 : 2} {extends | >3}",
+            &ctx
+        ),
+        "1 2   e"
+    );
+    assert_eq!(render("{endblock ?? 'none'}", &ctx), "none");
+
+    ctx.insert("block", Value::Bool(true));
+    assert_eq!(render("{block ? 'y' : 'n'}", &ctx), "y");
+}
+
+// ============================================
+// Macro Tests
+// ============================================
+
+#[test]
+fn test_macro_called_several_times() {
+    let template = CBTemplate::compile(
+        "{macro row(label, value)}| {label | <6}| {value | >5} |\n{endmacro}{row('Name', name)}{row('Score', score)}",
+    )
+    .unwrap();
+
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Ada"));
+    ctx.insert("score", Value::Int(97));
+
+    assert_eq!(
+        template.format(&ctx).unwrap(),
+        "| Name  |   Ada |\n| Score |    97 |\n"
+    );
+    assert_eq!(template.macros().collect::<Vec<_>>(), ["row"]);
+}
+
+#[test]
+fn test_macro_arguments_shadow_context() {
+    let mut ctx = Context::new();
+    ctx.insert("label", Value::static_str("outer"));
+    ctx.insert("suffix", Value::static_str("!"));
+
+    assert_eq!(
+        render(
+            "{macro shout(label)}{label}{suffix}{endmacro}{shout('inner')} {label}",
+            &ctx
+        ),
+        "inner! outer"
+    );
+}
+
+#[test]
+fn test_macro_in_expressions() {
+    let mut ctx = Context::new();
+    ctx.insert("new", Value::Bool(true));
+
+    assert_eq!(
+        render(
+            "{macro badge(text)}[{text}]{endmacro}{new ? badge('new') : ''}{badge(2.5)}",
+            &ctx
+        ),
+        "[new][2.5]"
+    );
+}
+
+#[test]
+fn test_macro_escaping() {
+    let mut template = CBTemplate::compile(
+        "{macro link(href, text)}<a href=\"{href}\">{text}</a>{endmacro}{link(url, 'Tom & Jerry')}",
+    )
+    .unwrap();
+    template.set_escape(Escape::Html);
+
+    let mut ctx = Context::new();
+    ctx.insert("url", Value::static_str("/a?b=1&c=\"2\""));
+
+    assert_eq!(
+        template.format(&ctx).unwrap(),
+        "<a href=\"/a?b=1&amp;c=&quot;2&quot;\">Tom & Jerry</a>"
+    );
+}
+
+#[test]
+fn test_macro_arity_mismatch() {
+    let template = CBTemplate::compile("{macro pair(a, b)}{a}{b}{endmacro}{pair(1)}").unwrap();
+
+    assert!(matches!(
+        template.format(&Context::new()).map_err(DirectiveError::into_root),
+        Err(DirectiveError::ArityMismatch { function, expected: 2, found: 1 }) if function == "pair"
+    ));
+}
+
+#[test]
+fn test_macro_shadows_function() {
+    let mut env = Environment::new();
+    env.register("greet", |name: Cow<'static, str>| {
+        format!("function {}", name)
+    });
+
+    let template =
+        CBTemplate::compile("{greet('a')} {macro greet(name)}macro {name}{endmacro}").unwrap();
+
+    assert_eq!(
+        template.format_with(&Context::new(), &env).unwrap(),
+        "macro a "
+    );
+}
+
+#[test]
+fn test_macro_import() {
+    let loader = memory_loader(&[
+        (
+            "forms",
+            "{macro field(name)}<{input(name)}>{endmacro}{macro input(name)}input {name}{endmacro}{macro note()}shared{endmacro}",
+        ),
+        (
+            "page",
+            "{import forms}{macro note()}local{endmacro}{field('email')} {note()}",
+        ),
+    ]);
+
+    let page = loader.get("page").unwrap();
+    assert_eq!(page.imports().collect::<Vec<_>>(), ["forms"]);
+
+    assert_eq!(
+        loader
+            .render("page", &Context::new(), &Environment::new())
+            .unwrap(),
+        "<input email> local"
+    );
+}
+
+#[test]
+fn test_macro_import_cycle() {
+    let loader = memory_loader(&[("a", "{import b}"), ("b", "{import a}")]);
+
+    assert!(matches!(loader.get("a"), Err(LoadError::Cycle { .. })));
+}
+
+#[test]
+fn test_macro_keywords_stay_variables() {
+    let mut ctx = Context::new();
+    ctx.insert("macro", Value::Int(1));
+    ctx.insert("import", Value::Int(2));
+    ctx.insert("endmacro", Value::Int(3));
+
+    assert_eq!(
+        render(
+            "{macro} {macro == 1 ? 'y' : 'n'} {import + 1} {endmacro * 2} {import | >2}",
+            &ctx
+        ),
+        "1 y 3 6  2"
+    );
+}
+
+#[test]
+fn test_macro_syntax_errors() {
+    assert!(matches!(
+        CBTemplate::compile("{macro m()}body"),
+        Err(TemplateError::UnclosedMacro { name }) if name == "m"
+    ));
+
+    for source in [
+        "{endmacro}",
+        "{macro m()}{macro n()}{endmacro}{endmacro}",
+        "{macro m()}{endmacro}{macro m()}{endmacro}",
+        "{macro m(a, a)}{endmacro}",
+        "{macro m(a,)}{endmacro}",
+        "{macro m}{endmacro}",
+        "{macro m()}{block b}{endblock}{endmacro}",
+    ] {
+        assert!(
+            matches!(
+                CBTemplate::compile(source),
+                Err(TemplateError::DirectiveParsing { .. })
+            ),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_locals_shadow_context() {
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(1));
+    ctx.insert("b", Value::Int(2));
+
+    let env = Environment::new();
+    let root = LayeredContext::new(&ctx);
+    let outer = Locals::new(vec![("a".into(), Value::Int(10))]);
+    let inner = Locals::new(vec![("b".into(), Value::Int(20))]);
+    let outer_vars = root.with_locals(&outer);
+    let scope = Scope::new(&ctx, &env).with_variables(outer_vars.with_locals(&inner));
+
+    assert!(matches!(scope.get("a"), Some(Value::Int(10))));
+    assert!(matches!(scope.get("b"), Some(Value::Int(20))));
+    assert!(scope.variables().layer().is_none());
+    assert!(matches!(scope.context().get("b"), Some(Value::Int(2))));
+    assert!(scope.context().layer().is_some());
+}
+
+// ============================================
+// Arithmetic Tests
+// ============================================
+
+#[test]
+fn test_arithmetic_precedence() {
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::Int(2));
+    ctx.insert("b", Value::Int(3));
+    ctx.insert("c", Value::Int(4));
+
+    assert_eq!(render("{a + b * c}", &ctx), "14");
+    assert_eq!(render("{(a + b) * c}", &ctx), "20");
+    assert_eq!(render("{c - b - a}", &ctx), "-1");
+    assert_eq!(render("{c / a - -1}", &ctx), "3");
+    assert_eq!(render("{a * b > c ? 'big' : 'small'}", &ctx), "big");
+    assert_eq!(render("{missing ?? 0 + a}", &ctx), "2");
+    assert_eq!(render("{(missing ?? 1) + a}", &ctx), "3");
+}
+
+#[test]
+fn test_unary_minus_on_operands() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(3));
+    ctx.insert("price", Value::Float(2.5));
+
+    assert_eq!(render("{-n}", &ctx), "-3");
+    assert_eq!(render("{-(n + 1)}", &ctx), "-4");
+    assert_eq!(render("{-price * 2}", &ctx), "-5.0");
+    assert_eq!(render("{n - -n}", &ctx), "6");
+    assert_eq!(render("{+n}", &ctx), "3");
+    assert_eq!(render("{-n < 0 ? 'neg' : 'pos'}", &ctx), "neg");
+
+    assert!(matches!(
+        CBTemplate::compile("{n * -}"),
+        Err(TemplateError::DirectiveParsing { .. })
+    ));
+}
+
+#[test]
+fn test_deeply_nested_operands() {
+    let mut ctx = Context::new();
+    ctx.insert("x", Value::Int(1));
+
+    let parens = |depth: usize| format!("{{{}x{}}}", "(".repeat(depth), ")".repeat(depth));
+    let signs = |depth: usize| format!("{{{}x}}", "-".repeat(depth));
+    let chain = |terms: usize| format!("{{x{}}}", " + x".repeat(terms - 1));
+
+    assert_eq!(render(&parens(50), &ctx), "1");
+    assert_eq!(render(&signs(50), &ctx), "1");
+    assert_eq!(render(&chain(50), &ctx), "50");
+
+    for source in [parens(100_000), signs(100_000), chain(100_000)] {
+        assert!(matches!(
+            CBTemplate::compile(&source),
+            Err(TemplateError::DirectiveParsing { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_arithmetic_types() {
+    let mut ctx = Context::new();
+    ctx.insert("price", Value::Float(2.5));
+    ctx.insert("qty", Value::Int(3));
+    ctx.insert("first", Value::static_str("Ada"));
+
+    assert_eq!(render("{price * qty}", &ctx), "7.5");
+    assert_eq!(render("{7 / 2}", &ctx), "3");
+    assert_eq!(render("{7.0 / 2}", &ctx), "3.5");
+    assert_eq!(render("{first + ' ' + 'Lovelace'}", &ctx), "Ada Lovelace");
+    assert_eq!(render("{price * qty | .2}", &ctx), "7.50");
+}
+
+#[test]
+fn test_arithmetic_errors() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(i64::MAX));
+
+    let eval = |source: &str| {
+        CBTemplate::compile(source)
+            .unwrap()
+            .format(&ctx)
+            .map_err(DirectiveError::into_root)
+    };
+
+    assert!(matches!(
+        eval("{1 / 0}"),
+        Err(DirectiveError::DivisionByZero)
+    ));
+    assert!(matches!(
+        eval("{1.5 / 0.0}"),
+        Err(DirectiveError::DivisionByZero)
+    ));
+    assert!(matches!(
+        eval("{n + 1}"),
+        Err(DirectiveError::Overflow { op: "+" })
+    ));
+    assert!(matches!(
+        eval("{n * 'two'}"),
+        Err(DirectiveError::InvalidOperands { left, op: "*", right })
+            if left == "integer" && right == "string"
+    ));
+    assert!(matches!(
+        eval("{'a' - 'b'}"),
+        Err(DirectiveError::InvalidOperands { .. })
+    ));
+
+    for source in ["{a +}", "{(a + b}", "{()}"] {
+        assert!(
+            matches!(
+                CBTemplate::compile(source),
+                Err(TemplateError::DirectiveParsing { .. })
+            ),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_arithmetic_escapes_result() {
+    let mut ctx = Context::new();
+    ctx.insert("tag", Value::static_str("<b>"));
+
+    assert_eq!(
+        escaped(Escape::Html, "{'<i>' + tag}", &ctx),
+        "&lt;i&gt;&lt;b&gt;"
+    );
+    assert_eq!(escaped(Escape::Html, "{'<i>' + '</i>'}", &ctx), "<i></i>");
+}
+
+// ============================================
+// Assignment Tests
+// ============================================
+
+#[test]
+fn test_set_binds_local() {
+    let mut ctx = Context::new();
+    ctx.insert("price", Value::Float(2.5));
+    ctx.insert("qty", Value::Int(4));
+
+    assert_eq!(
+        render(
+            "{set total = price * qty}{total} ({total | .2}, {total > 5 ? 'big' : 'small'})",
+            &ctx
+        ),
+        "10.0 (10.00, big)"
+    );
+    assert!(!ctx.contains_key("total"));
+}
+
+#[test]
+fn test_set_visibility() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(1));
+
+    // Only later directives see the variable, and it shadows the context
+    let tmpl = CBTemplate::compile("{n} {set n = n + 1}{n} {set n = n * 10}{n}").unwrap();
+    assert_eq!(tmpl.format(&ctx).unwrap(), "1 2 20");
+
+    // Rendering again starts over
+    assert_eq!(tmpl.format(&ctx).unwrap(), "1 2 20");
+
+    assert!(matches!(
+        CBTemplate::compile("{total}{set total = 1}")
+            .unwrap()
+            .format(&ctx)
+            .map_err(DirectiveError::into_root),
+        Err(DirectiveError::NotFound { name, .. }) if name == "total"
+    ));
+}
+
+#[test]
+fn test_set_in_macro_is_local() {
+    let tmpl = CBTemplate::compile(
+        "{macro double(x)}{set y = x * 2}{y}{endmacro}{set y = 'outer'}{double(4)} {y}",
+    )
+    .unwrap();
+
+    assert_eq!(tmpl.format(&Context::new()).unwrap(), "8 outer");
+}
+
+#[test]
+fn test_set_visible_to_includes() {
+    let loader = memory_loader(&[
+        ("row", "{label}: {total}"),
+        ("page", "{set total = 3 * 4}{> row}"),
+    ]);
+
+    let mut ctx = Context::new();
+    ctx.insert("label", Value::static_str("Total"));
+
+    assert_eq!(
+        loader.render("page", &ctx, &Environment::new()).unwrap(),
+        "Total: 12"
+    );
+}
+
+#[test]
+fn test_set_keeps_safe_values() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("<Ann>"));
+
+    assert_eq!(
+        escaped(
+            Escape::Html,
+            "{set open = '<b>'}{set who = name}{open}{who}</b>",
+            &ctx
+        ),
+        "<b>&lt;Ann&gt;</b>"
+    );
+}
+
+#[test]
+fn test_set_missing_variable_policy() {
+    let tmpl = CBTemplate::compile("{set x = missing}[{x}]").unwrap();
+
+    assert_eq!(
+        tmpl.format_with(&Context::new(), &missing_env(MissingVariable::Original))
+            .unwrap(),
+        "[{x}]"
+    );
+    assert_eq!(
+        tmpl.format_with(&Context::new(), &missing_env(MissingVariable::Empty))
+            .unwrap(),
+        "[]"
+    );
+    assert_eq!(
+        tmpl.format_with(
+            &Context::new(),
+            &missing_env(MissingVariable::Placeholder("???".into()))
+        )
+        .unwrap(),
+        "[???]"
+    );
+}
+
+#[test]
+fn test_set_syntax() {
+    let mut ctx = Context::new();
+    ctx.insert("set", Value::static_str("kept"));
+
+    // Without an assignment `set` is an ordinary variable
+    assert_eq!(render("{set}", &ctx), "kept");
+
+    assert!(matches!(
+        CBTemplate::compile("{set x =}"),
+        Err(TemplateError::DirectiveParsing { .. })
+    ));
+
+    match CBTemplate::compile("{set x = max(1,}") {
+        Err(TemplateError::DirectiveParsing { span, .. }) => assert_eq!(span, Span::new(15, 15)),
+        other => panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+// ============================================
+// Layered Context Tests
+// ============================================
+
+fn globals() -> Context {
+    let mut ctx = Context::new();
+    ctx.insert("company", Value::static_str("Acme"));
+    ctx.insert("base_url", Value::static_str("https://acme.test"));
+    ctx.insert("name", Value::static_str("customer"));
+    ctx
+}
+
+#[test]
+fn test_layered_context_lookup() {
+    let globals = globals();
+    let base = LayeredContext::new(&globals);
+
+    let mut request = Context::new();
+    request.insert("name", Value::static_str("Alice"));
+    let ctx = base.child(&request);
+
+    assert!(matches!(ctx.get("name"), Some(Value::Str(name)) if name == "Alice"));
+    assert!(matches!(ctx.get("company"), Some(Value::Str(name)) if name == "Acme"));
+    assert!(ctx.get("missing").is_none());
+    assert!(ctx.parent().is_some());
+    assert!(base.parent().is_none());
+    assert!(!ctx.layer().unwrap().contains_key("company"));
+}
+
+#[test]
+fn test_layered_context_render() {
+    let globals = globals();
+    let base = LayeredContext::new(&globals);
+    let env = Environment::new();
+    let tmpl = CBTemplate::compile("{company}: hi {name}, see {base_url}/{page}").unwrap();
+
+    for (name, page) in [("Alice", "orders"), ("Bob", "invoices")] {
+        let mut request = Context::new();
+        request.insert("name", Value::static_str(name));
+        request.insert("page", Value::static_str(page));
+
+        assert_eq!(
+            tmpl.format_layered(&base.child(&request), &env).unwrap(),
+            format!("Acme: hi {}, see https://acme.test/{}", name, page)
+        );
+    }
+
+    // The shared layer is untouched
+    assert!(matches!(globals.get("name"), Some(Value::Str(name)) if name == "customer"));
+}
+
+#[test]
+fn test_layered_context_depth_and_locals() {
+    let globals = globals();
+    let mut tenant = Context::new();
+    tenant.insert("company", Value::static_str("Globex"));
+    let mut request = Context::new();
+    request.insert("qty", Value::Int(2));
+
+    let base = LayeredContext::new(&globals);
+    let tenant = base.child(&tenant);
+    let ctx = tenant.child(&request);
+
+    let tmpl = CBTemplate::compile(
+        "{macro greet(name)}{company} greets {name}{endmacro}\
+         {greet('Bob')}; {set company = 'Initech'}{company} x{qty}; {name}",
+    )
+    .unwrap();
+
+    assert_eq!(
+        tmpl.format_layered(&ctx, &Environment::new()).unwrap(),
+        "Globex greets Bob; Initech x2; customer"
+    );
+}
+
+#[test]
+fn test_layered_context_with_loader() {
+    let loader = memory_loader(&[
+        ("footer", "{company} - {base_url}"),
+        ("mail", "Dear {name},\n{> footer}"),
+    ]);
+
+    let globals = globals();
+    let base = LayeredContext::new(&globals);
+    let mut request = Context::new();
+    request.insert("name", Value::static_str("Alice"));
+
+    assert_eq!(
+        loader
+            .render_layered("mail", &base.child(&request), &Environment::new())
+            .unwrap(),
+        "Dear Alice,\nAcme - https://acme.test"
+    );
+
+    let env = Environment::new();
+    let scope = Scope::layered(&base, &env);
+    assert!(scope.get("company").is_some());
+    assert!(scope.context().layer().unwrap().contains_key("company"));
+}
+
+// ============================================
+// Repeat Body Tests
+// ============================================
+
+#[test]
+fn test_repeat_body_index_and_separator() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(3));
+
+    assert_eq!(
+        render("VALUES ({repeat n as i sep ', '}${i + 1}{endrepeat})", &ctx),
+        "VALUES ($1, $2, $3)"
+    );
+    assert_eq!(render("{repeat 3}[{index}]{endrepeat}", &ctx), "[0][1][2]");
+    assert_eq!(render("{repeat n - 1 sep '|'}-{endrepeat}", &ctx), "-|-");
+}
+
+#[test]
+fn test_repeat_body_counts() {
+    let ctx = Context::new();
+
+    assert_eq!(render("<{repeat 0 sep ','}x{endrepeat}>", &ctx), "<>");
+    assert_eq!(render("<{repeat -2}x{endrepeat}>", &ctx), "<>");
+    assert_eq!(render("<{repeat 1 sep ','}x{endrepeat}>", &ctx), "<x>");
+}
+
+#[test]
+fn test_repeat_body_nested() {
+    let ctx = Context::new();
+
+    assert_eq!(
+        render(
+            "{repeat 2 as row sep '\\n'}{repeat 3 as col sep ','}{row * 3 + col}{endrepeat}{endrepeat}",
+            &ctx
+        ),
+        "0,1,2\n3,4,5"
+    );
+}
+
+#[test]
+fn test_repeat_body_csv_header() {
+    let mut ctx = Context::new();
+    ctx.insert("columns", Value::Int(3));
+    ctx.insert("prefix", Value::static_str("col"));
+
+    assert_eq!(
+        render(
+            "{repeat columns as i sep ','}{set name = prefix + '_'}{name}{i}{endrepeat}",
+            &ctx
+        ),
+        "col_0,col_1,col_2"
+    );
+}
+
+#[test]
+fn test_repeat_body_escaping() {
+    let mut ctx = Context::new();
+    ctx.insert("cell", Value::static_str("<td>"));
+    ctx.insert("sep", Value::static_str("&"));
+
+    // The body is escaped once; a literal separator is left alone, a variable is escaped
+    assert_eq!(
+        escaped(Escape::Html, "{repeat 2 sep '<br>'}{cell}{endrepeat}", &ctx),
+        "&lt;td&gt;<br>&lt;td&gt;"
+    );
+    assert_eq!(
+        escaped(Escape::Html, "{repeat 2 sep sep}x{endrepeat}", &ctx),
+        "x&amp;x"
+    );
+}
+
+#[test]
+fn test_repeat_body_in_macro_and_block() {
+    let tmpl = CBTemplate::compile(
+        "{macro stars(n)}{repeat n}*{endrepeat}{endmacro}{block body}{stars(3)}{endblock}",
+    )
+    .unwrap();
+
+    assert_eq!(tmpl.format(&Context::new()).unwrap(), "***");
+}
+
+#[test]
+fn test_repeat_keywords_stay_variables() {
+    let mut ctx = Context::new();
+    ctx.insert("repeat", Value::Int(2));
+    ctx.insert("endrepeat", Value::Int(3));
+
+    assert_eq!(
+        render(
+            "{repeat} {repeat > 0 ? 'a' : 'b'} {repeat * 2} {endrepeat + 1} {endrepeat | >2}",
+            &ctx
+        ),
+        "2 a 4 4  3"
+    );
+}
+
+#[test]
+fn test_repeat_body_errors() {
+    let mut ctx = Context::new();
+    ctx.insert("word", Value::static_str("many"));
+
+    assert!(matches!(
+        CBTemplate::compile("{repeat word}x{endrepeat}")
+            .unwrap()
+            .format(&ctx)
+            .map_err(DirectiveError::into_root),
+        Err(DirectiveError::TypeError { name, .. }) if name == "word"
+    ));
+    assert!(matches!(
+        CBTemplate::compile("{repeat 2}x"),
+        Err(TemplateError::UnclosedRepeat)
+    ));
+
+    for source in [
+        "{endrepeat}",
+        "{repeat (}x{endrepeat}",
+        "{repeat 2 as}x{endrepeat}",
+        "{repeat 2 sep}x{endrepeat}",
+        "{repeat 2 by 3}x{endrepeat}",
+        "{repeat 2}{macro m()}{endmacro}{endrepeat}",
+        "{macro m()}{repeat 2}{endmacro}{endrepeat}",
+        "{repeat 2}{block b}{endblock}{endrepeat}",
+        "{block b}{repeat 2}{endblock}{endrepeat}",
+    ] {
+        assert!(
+            matches!(
+                CBTemplate::compile(source),
+                Err(TemplateError::DirectiveParsing { .. })
+            ),
+            "{}",
+            source
+        );
+    }
+
+    match CBTemplate::compile("{repeat 2 as 3}x{endrepeat}") {
+        Err(TemplateError::DirectiveParsing { span, .. }) => assert_eq!(span, Span::new(13, 14)),
+        other => panic!("unexpected result: {:?}", other.err()),
+    }
+
+    // Without a count `repeat` is an ordinary variable
+    ctx.insert("repeat", Value::static_str("again"));
+    assert_eq!(render("{repeat}", &ctx), "again");
+}
+
+// ============================================
+// Limit Tests
+// ============================================
+
+fn limited(limits: Limits) -> Environment {
+    let mut env = Environment::new();
+    env.set_limits(limits);
+    env
+}
+
+fn limit_error(source: &str, ctx: &Context, env: &Environment) -> Option<(Limit, u64)> {
+    match CBTemplate::compile(source)
+        .unwrap()
+        .format_with(ctx, env)
+        .map_err(DirectiveError::into_root)
+    {
+        Err(DirectiveError::LimitExceeded { limit, max }) => Some((limit, max)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_limits_default() {
+    assert_eq!(Limits::default().max_depth, Some(64));
+    assert_eq!(Limits::default().max_output, Some(64 << 20));
+    assert_eq!(Limits::default().max_repeat, Some(1_000_000));
+    assert_eq!(Limits::default().fuel, None);
+    assert_eq!(*Environment::new().limits(), Limits::default());
+    assert_eq!(Limits::UNLIMITED.max_depth, None);
+}
+
+#[test]
+fn test_huge_repeat_count_fails() {
+    let source = "{'ab':9223372036854775807}";
+
+    assert_eq!(
+        limit_error(source, &Context::new(), &Environment::default()),
+        Some((Limit::Repeat, 1_000_000))
+    );
+    assert_eq!(
+        limit_error(source, &Context::new(), &limited(Limits::UNLIMITED)),
+        Some((Limit::Output, isize::MAX as u64))
+    );
+    assert!(matches!(
+        limit_error(
+            "{'abc':9223372036854775807}",
+            &Context::new(),
+            &limited(Limits::UNLIMITED)
+        ),
+        Some((Limit::Output, _))
+    ));
+}
+
+#[test]
+fn test_negative_repeat_count() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Int(-1));
+
+    assert_eq!(render("[{'x':-1}{'y':n}]", &ctx), "[]");
+}
+
+#[test]
+fn test_limit_repeat_count() {
+    let env = limited(Limits {
+        max_repeat: Some(10),
+        ..Limits::default()
+    });
+    let ctx = Context::new();
+
+    assert_eq!(
+        limit_error("{'x':11}", &ctx, &env),
+        Some((Limit::Repeat, 10))
+    );
+    assert_eq!(
+        limit_error("{repeat 11}x{endrepeat}", &ctx, &env),
+        Some((Limit::Repeat, 10))
+    );
+    assert_eq!(
+        CBTemplate::compile("{'x':10}")
+            .unwrap()
+            .format_with(&ctx, &env)
+            .unwrap(),
+        "xxxxxxxxxx"
+    );
+}
+
+#[test]
+fn test_limit_output_size() {
+    let env = limited(Limits {
+        max_output: Some(100),
+        max_repeat: None,
+        ..Limits::default()
+    });
+    let mut ctx = Context::new();
+    ctx.insert("text", Value::owned_str("y".repeat(60)));
+
+    // Checked before allocating the repetition
+    assert_eq!(
+        limit_error("{'x':1000000000000}", &ctx, &env),
+        Some((Limit::Output, 100))
+    );
+    assert_eq!(
+        limit_error("{text}{text}", &ctx, &env),
+        Some((Limit::Output, 100))
+    );
+    assert_eq!(
+        limit_error("{repeat 50}ab{endrepeat}ab", &ctx, &env),
+        Some((Limit::Output, 100))
+    );
+    assert_eq!(
+        limit_error("{text | >1000000000}", &ctx, &env),
+        Some((Limit::Output, 100))
+    );
+    assert_eq!(limit_error("{text}", &ctx, &env), None);
+}
+
+#[test]
+fn test_limit_macro_recursion() {
+    let source = "{macro down(n)}{n > 0 ? down(n - 1) : 'done'}{endmacro}{down(depth)}";
+    let mut ctx = Context::new();
+
+    ctx.insert("depth", Value::Int(10));
+    assert_eq!(render(source, &ctx), "done");
+
+    ctx.insert("depth", Value::Int(1000));
+    assert_eq!(
+        limit_error(source, &ctx, &Environment::new()),
+        Some((Limit::Depth, 64))
+    );
+
+    let env = limited(Limits {
+        max_depth: Some(5),
+        ..Limits::default()
+    });
+    ctx.insert("depth", Value::Int(5));
+    assert_eq!(limit_error(source, &ctx, &env), Some((Limit::Depth, 5)));
+}
+
+#[test]
+fn test_limit_include_depth() {
+    let loader = memory_loader(&[("a", "a{> b}"), ("b", "b{> c}"), ("c", "c")]);
+    let ctx = Context::new();
+
+    assert_eq!(
+        loader.render("a", &ctx, &Environment::new()).unwrap(),
+        "abc"
+    );
+
+    let env = limited(Limits {
+        max_depth: Some(1),
+        ..Limits::default()
+    });
+
+    assert!(matches!(
+        loader.render("a", &ctx, &env),
+        Err(LoadError::Render { source, .. })
+            if matches!(source.root(), DirectiveError::LimitExceeded { limit: Limit::Depth, max: 1 })
+    ));
+}
+
+#[test]
+fn test_limit_fuel() {
+    let env = limited(Limits {
+        fuel: Some(50),
+        ..Limits::default()
+    });
+    let ctx = Context::new();
+
+    assert_eq!(
+        limit_error("{repeat 100}{index}{endrepeat}", &ctx, &env),
+        Some((Limit::Fuel, 50))
+    );
+
+    // Each render gets the full amount
+    let tmpl = CBTemplate::compile("{repeat 10}{index * 2}{endrepeat}").unwrap();
+    assert_eq!(tmpl.format_with(&ctx, &env).unwrap(), "024681012141618");
+    assert_eq!(tmpl.format_with(&ctx, &env).unwrap(), "024681012141618");
+
+    // Included templates draw from the same fuel
+    let loader = memory_loader(&[
+        ("part", "{repeat 30}{index}{endrepeat}"),
+        ("page", "{> part}{> part}"),
+    ]);
+    assert!(loader.render("part", &ctx, &env).is_ok());
+    assert!(matches!(
+        loader.render("page", &ctx, &env),
+        Err(LoadError::Render { source, .. })
+            if matches!(source.root(), DirectiveError::LimitExceeded { limit: Limit::Fuel, .. })
+    ));
+}
+
+// ============================================
+// Error Collection Tests
+// ============================================
+
+fn marker_env(marker: &'static str) -> Environment {
+    let mut env = Environment::new();
+    env.set_error_marker(marker);
+    env
+}
+
+#[test]
+fn test_collect_errors_continues() {
+    let mut ctx = Context::new();
+    ctx.insert("b", Value::Int(2));
+    ctx.insert("word", Value::static_str("x"));
+
+    let tmpl = CBTemplate::compile("{a}, {b}, {word:word}, {shout(b)}, {b / 0}").unwrap();
+    let rendered = tmpl.format_collect(&ctx, &marker_env("[!]"));
+
+    assert_eq!(rendered.output, "[!], 2, [!], [!], [!]");
+    assert!(!rendered.is_ok());
+
+    let errors: Vec<_> = rendered
+        .errors
+        .iter()
+        .map(|error| error.location.as_ref().unwrap())
+        .map(|location| (location.directive.as_str(), location.span))
+        .collect();
+
+    assert_eq!(
+        errors,
+        [
+            ("{a}", Span::new(1, 2)),
+            ("{word:word}", Span::new(11, 20)),
+            ("{shout(b)}", Span::new(24, 32)),
+            ("{b / 0}", Span::new(36, 41)),
+        ]
+    );
+    assert!(matches!(
+        rendered.errors[0].error,
+        DirectiveError::NotFound { .. }
+    ));
+    assert!(matches!(
+        rendered.errors[3].error,
+        DirectiveError::DivisionByZero
+    ));
+}
+
+#[test]
+fn test_collect_errors_success() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::static_str("Ann"));
+
+    let rendered = CBTemplate::compile("Hi {name}!")
+        .unwrap()
+        .format_collect(&ctx, &Environment::new());
+
+    assert!(rendered.is_ok());
+    assert_eq!(rendered.output, "Hi Ann!");
+}
+
+#[test]
+fn test_collect_errors_default_marker_and_policy() {
+    let tmpl = CBTemplate::compile("[{a}][{b + 1}]").unwrap();
+
+    let rendered = tmpl.format_collect(&Context::new(), &Environment::new());
+    assert_eq!(rendered.output, "[][]");
+    assert_eq!(rendered.errors.len(), 2);
+
+    // Missing variables handled by the policy are not errors
+    let mut env = missing_env(MissingVariable::Original);
+    env.set_error_marker("!");
+    let rendered = tmpl.format_collect(&Context::new(), &env);
+    assert_eq!(rendered.output, "[{a}][{b + 1}]");
+    assert!(rendered.is_ok());
+}
+
+#[test]
+fn test_collect_errors_nested() {
+    let loader = memory_loader(&[
+        ("row", "<{missing}>"),
+        (
+            "page",
+            "{macro m(x)}({x * 2}){endmacro}{m('a')}{repeat 2}{> row}{endrepeat}",
+        ),
+    ]);
+
+    let rendered = loader
+        .render_collect("page", &Context::new(), &marker_env("?"))
+        .unwrap();
+
+    assert_eq!(rendered.output, "(?)<?><?>");
+
+    let directives: Vec<_> = rendered
+        .errors
+        .iter()
+        .map(|error| error.location.as_ref().unwrap().directive.as_str())
+        .collect();
+    assert_eq!(directives, ["{x * 2}", "{missing}", "{missing}"]);
+    assert_eq!(
+        rendered.errors[1].location.as_ref().unwrap().span,
+        Span::new(2, 9)
+    );
+
+    // Without a loader, includes fail like any other directive
+    let rendered = CBTemplate::compile("a{> row}b")
+        .unwrap()
+        .format_collect(&Context::new(), &marker_env("?"));
+    assert_eq!(rendered.output, "a?b");
+    assert!(matches!(
+        rendered.errors[0].error,
+        DirectiveError::Include { .. }
+    ));
+
+    assert!(matches!(
+        loader.render_collect("missing", &Context::new(), &Environment::new()),
+        Err(LoadError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_collect_errors_stops_at_limits() {
+    let mut env = marker_env("?");
+    env.set_limits(Limits {
+        max_repeat: Some(5),
+        ..Limits::default()
+    });
+
+    let rendered = CBTemplate::compile("{a}{repeat 2}{'x':10}{endrepeat}{b}")
+        .unwrap()
+        .format_collect(&Context::new(), &env);
+
+    assert_eq!(rendered.output, "");
+    assert_eq!(rendered.errors.len(), 2);
+    assert!(matches!(
+        rendered.errors[1].error,
+        DirectiveError::LimitExceeded {
+            limit: Limit::Repeat,
+            ..
+        }
+    ));
+    assert_eq!(
+        rendered.errors[1].location.as_ref().unwrap().directive,
+        "{'x':10}"
+    );
+}
+
+#[test]
+fn test_collect_errors_unresolved_extends() {
+    let rendered = CBTemplate::compile("{extends base}{block a}x{endblock}")
+        .unwrap()
+        .format_collect(&Context::new(), &Environment::new());
+
+    assert_eq!(rendered.output, "");
+    assert!(matches!(
+        &rendered.errors[..],
+        [RenderError {
+            error: DirectiveError::UnresolvedExtends { .. },
+            location: Some(location),
+        }] if location.directive == "{extends base}"
+    ));
+}
+
+#[test]
+fn test_render_error_display() {
+    let rendered = CBTemplate::compile("ab {shout(x)}")
+        .unwrap()
+        .format_collect(&Context::new(), &Environment::new());
+
+    assert_eq!(
+        rendered.errors[0].to_string(),
+        "{shout(x)} at 4..12: Function 'shout' is not registered in the environment"
+    );
+}
+
+// ============================================
+// Render Error Location Tests
+// ============================================
+
+#[test]
+fn test_render_error_location() {
+    let err = CBTemplate::compile("Hi {name}!")
+        .unwrap()
+        .format(&Context::new())
+        .unwrap_err();
+
+    let location = err.location().unwrap();
+    assert_eq!(location.directive, "{name}");
+    assert_eq!(location.span, Span::new(4, 8));
+    assert_eq!(location.template, None);
+    assert!(matches!(err.root(), DirectiveError::NotFound { name, .. } if name == "name"));
+    assert_eq!(
+        err.to_string(),
+        "{name} at 4..8: Variable 'name' was not found in the context while being used as 'string'"
+    );
+}
+
+#[test]
+fn test_render_error_location_is_innermost_directive() {
+    let template =
+        CBTemplate::compile("{macro row()}<{cell}>{endmacro}{row()} {repeat 2}{x}{endrepeat}")
+            .unwrap();
+
+    let mut ctx = Context::new();
+    let err = template.format(&ctx).unwrap_err();
+    assert_eq!(err.location().unwrap().directive, "{cell}");
+    assert!(
+        matches!(err, DirectiveError::At { source, .. } if matches!(*source, DirectiveError::NotFound { .. }))
+    );
+
+    ctx.insert("cell", Value::Int(1));
+    let err = template.format(&ctx).unwrap_err();
+    assert_eq!(err.location().unwrap().directive, "{x}");
+}
+
+#[test]
+fn test_render_error_location_of_limits_and_extends() {
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::static_str("abc"));
+    ctx.insert("b", Value::static_str("def"));
+    ctx.insert("c", Value::static_str("ghi"));
+
+    let template = CBTemplate::compile("{a}-{b}-{c}").unwrap();
+
+    let env = limited(Limits {
+        fuel: Some(2),
+        ..Limits::default()
+    });
+    let err = template.format_with(&ctx, &env).unwrap_err();
+    assert_eq!(err.location().unwrap().directive, "{b}");
+    assert!(matches!(
+        err.root(),
+        DirectiveError::LimitExceeded {
+            limit: Limit::Fuel,
+            ..
+        }
+    ));
+
+    let env = limited(Limits {
+        max_output: Some(5),
+        ..Limits::default()
+    });
+    let err = template.format_with(&ctx, &env).unwrap_err();
+    assert_eq!(err.location().unwrap().directive, "{b}");
+    assert!(matches!(
+        err.root(),
+        DirectiveError::LimitExceeded {
+            limit: Limit::Output,
+            ..
+        }
+    ));
+
+    let err = CBTemplate::compile("x{extends base}")
+        .unwrap()
+        .format(&ctx)
+        .unwrap_err();
+    let location = err.location().unwrap();
+    assert_eq!(location.directive, "{extends base}");
+    assert_eq!(location.span, Span::new(2, 14));
+    assert!(matches!(err.root(), DirectiveError::UnresolvedExtends { name } if name == "base"));
+}
+
+#[test]
+fn test_render_error_template_name() {
+    let loader = memory_loader(&[
+        ("base", "<{block body}{missing}{endblock}>{footer}"),
+        ("page", "{extends base}{block body}{> part}{endblock}"),
+        ("part", "[{y}]"),
+    ]);
+    let env = Environment::new();
+    let mut ctx = Context::new();
+
+    let template_of = |ctx: &Context| match loader.render("page", ctx, &env) {
+        Err(LoadError::Render { source, .. }) => source.location().unwrap().template.clone(),
+        _ => None,
+    };
+
+    // The include is written in 'page', but the failing directive is in 'part'
+    assert_eq!(template_of(&ctx).as_deref(), Some("part"));
+
+    ctx.insert("y", Value::Int(1));
+    assert_eq!(template_of(&ctx).as_deref(), Some("base"));
+
+    let err = loader.render("page", &ctx, &env).unwrap_err();
+    assert!(
+        err.to_string().starts_with(
+            "Failed to render template 'page': {footer} at 34..40 in template 'base': "
+        )
+    );
+
+    let rendered = loader.render_collect("page", &ctx, &env).unwrap();
+    assert_eq!(
+        rendered.errors[0]
+            .location
+            .as_ref()
+            .unwrap()
+            .template
+            .as_deref(),
+        Some("base")
+    );
+}
+
+#[test]
+fn test_expression_type_error_names_expression() {
+    let mut ctx = Context::new();
+    ctx.insert("s", Value::static_str("a"));
+
+    let err = CBTemplate::compile("{repeat (s + s) ?? 1}x{endrepeat}")
+        .unwrap()
+        .format(&ctx)
+        .unwrap_err();
+
+    assert!(matches!(
+        err.root(),
+        DirectiveError::TypeError { name, .. } if name == "(s + s) ?? 1"
+    ));
+}
+
+#[test]
+fn test_argument_display() {
+    let sum = Argument::arithmetic(
+        Argument::variable(Cow::Borrowed("price")),
+        ArithmeticOp::Multiply,
+        Argument::literal(Value::Int(2)),
+    );
+
+    assert_eq!(sum.to_string(), "price * 2");
+    assert_eq!(
+        Argument::literal(Value::static_str("it's")).to_string(),
+        "'it\\'s'"
+    );
+    assert_eq!(Argument::literal(Value::Float(1.0)).to_string(), "1.0");
+}